@@ -1,17 +1,21 @@
 // src/main.rs
 
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
 use winit::{
+    dpi::{LogicalSize, PhysicalPosition},
     event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::Window,
+    event_loop::{ControlFlow, EventLoop, EventLoopProxy},
+    window::{Fullscreen, Theme, UserAttentionType, Window, WindowBuilder, WindowLevel},
 };
 
 // eGUI imports
 use egui::{
-    Color32, Context, FontFamily, FontId, Rounding, ScrollArea,
+    Color32, Context, FontFamily, FontId, Key, KeyboardShortcut, Modifiers, Rounding, ScrollArea,
     Stroke, Style, TextStyle, ViewportId, Visuals, 
     Vec2,
 };
@@ -25,554 +29,5188 @@ use egui_winit::State as EguiWinitState;
 enum AppMessage {
     TaskCompleted(String),
     NewLine(String),
+    ShowDialog(DialogRequest),
+    TaskFinished(TaskId, Result<(), String>),
+    TaskProgress(TaskId, TaskProgress),
+    StressFinished(StressReport),
+    SetInput(String),
+    RunCommand(String),
+    Remote(RemoteAction),
+    ImageAttached(ImageAttachment),
+    /// An input event from outside winit. Only the native macOS Edit menu sends these.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    Input(egui::Event),
 }
 
-/// Manages the terminal's text content with scrolling support.
-struct TextBuffer {
-    lines: Vec<String>,
-    max_lines: usize,
-    scroll_position: usize,
+/// Result of a `stress lines` run, as measured by the sending side.
+#[derive(Debug)]
+struct StressReport {
+    requested: usize,
+    sent: usize,
+    dropped: usize,
+    elapsed: Duration,
 }
 
-impl TextBuffer {
-    fn new(max_lines: usize) -> Self {
-        Self {
-            lines: Vec::with_capacity(max_lines),
-            max_lines,
-            scroll_position: 0,
+/// Floods the channel with `count` synthetic lines, at `rate` lines per second if given.
+/// Lines are sent with `try_send` so a full channel shows up as dropped messages.
+async fn run_stress(tx: mpsc::Sender<AppMessage>, count: usize, rate: Option<u32>) -> Result<(), String> {
+    let started = Instant::now();
+    let mut sent = 0;
+    let mut dropped = 0;
+    for i in 0..count {
+        if let Some(rate) = rate {
+            // Keep to a schedule instead of sleeping per line, so high rates stay reachable
+            let due = started + Duration::from_secs_f64(i as f64 / rate as f64);
+            if due > Instant::now() {
+                tokio::time::sleep_until(tokio::time::Instant::from_std(due)).await;
+            }
+        } else if i % 1000 == 0 {
+            tokio::task::yield_now().await;
         }
-    }
 
-    fn add_line(&mut self, line: String) {
-        if self.lines.len() >= self.max_lines {
-            self.lines.remove(0);
-            if self.scroll_position > 0 {
-                self.scroll_position -= 1;
-            }
+        let line = format!("[STRESS] line {} of {} | the quick brown fox jumps over the lazy dog", i + 1, count);
+        match tx.try_send(AppMessage::NewLine(line)) {
+            Ok(()) => sent += 1,
+            Err(mpsc::error::TrySendError::Full(_)) => dropped += 1,
+            Err(mpsc::error::TrySendError::Closed(_)) => return Err("UI closed during stress run".to_string()),
         }
-        self.lines.push(line);
-        // Auto-scroll to bottom when new line is added
-        self.scroll_position = self.lines.len().saturating_sub(self.max_lines);
     }
 
-    fn scroll_up(&mut self) {
-        if self.scroll_position > 0 {
-            self.scroll_position -= 1;
+    let report = StressReport {
+        requested: count,
+        sent,
+        dropped,
+        elapsed: started.elapsed(),
+    };
+    tx.send(AppMessage::StressFinished(report))
+        .await
+        .map_err(|_| "Failed to send stress report".to_string())
+}
+
+/// One step of a demo script.
+#[derive(Debug, Clone, PartialEq)]
+enum DemoStep {
+    /// Types a command into the input box, then runs it.
+    Type(String),
+    /// Prints a line as if it were command output.
+    Output(String),
+    Wait(Duration),
+}
+
+/// Delay between typed characters during demo playback.
+const DEMO_KEYSTROKE_DELAY: Duration = Duration::from_millis(40);
+
+/// Parses a demo script: one `type <command>`, `out <text>` or `wait <ms>` step per line.
+/// Blank lines and lines starting with `#` are ignored.
+fn parse_demo_script(script: &str) -> Result<Vec<DemoStep>, String> {
+    let mut steps = Vec::new();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim_end();
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
         }
+        let (directive, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let step = match directive {
+            "type" if rest.trim().is_empty() => return Err(format!("line {}: 'type' needs a command", number + 1)),
+            "type" => DemoStep::Type(rest.to_string()),
+            "out" => DemoStep::Output(rest.to_string()),
+            "wait" => match rest.trim().parse::<u64>() {
+                Ok(ms) => DemoStep::Wait(Duration::from_millis(ms)),
+                Err(_) => return Err(format!("line {}: invalid wait '{}'", number + 1, rest.trim())),
+            },
+            other => return Err(format!("line {}: unknown directive '{}'", number + 1, other)),
+        };
+        steps.push(step);
     }
+    Ok(steps)
+}
 
-    fn scroll_down(&mut self) {
-        let max_scroll = self.lines.len().saturating_sub(self.max_lines);
-        if self.scroll_position < max_scroll {
-            self.scroll_position += 1;
+/// Plays a demo script `times` times with fixed timing, so every run looks the same.
+async fn play_demo(
+    tx: mpsc::Sender<AppMessage>,
+    waker: UiWaker,
+    path: String,
+    times: usize,
+) -> Result<(), String> {
+    let script = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| format!("Failed to read demo script {}: {}", path, e))?;
+    let steps = parse_demo_script(&script)?;
+
+    let send = |message: AppMessage| {
+        let tx = tx.clone();
+        let waker = waker.clone();
+        async move {
+            tx.send(message).await.map_err(|_| "UI closed during demo".to_string())?;
+            // Wake the UI right away so typing animates smoothly instead of at the idle poll rate
+            waker.wake();
+            Ok::<(), String>(())
+        }
+    };
+
+    for _ in 0..times {
+        for step in &steps {
+            match step {
+                DemoStep::Type(command) => {
+                    for (index, _) in command.char_indices().skip(1) {
+                        send(AppMessage::SetInput(command[..index].to_string())).await?;
+                        tokio::time::sleep(DEMO_KEYSTROKE_DELAY).await;
+                    }
+                    send(AppMessage::SetInput(command.clone())).await?;
+                    tokio::time::sleep(DEMO_KEYSTROKE_DELAY).await;
+                    send(AppMessage::RunCommand(command.clone())).await?;
+                    send(AppMessage::SetInput(String::new())).await?;
+                }
+                DemoStep::Output(text) => send(AppMessage::NewLine(text.clone())).await?,
+                DemoStep::Wait(duration) => tokio::time::sleep(*duration).await,
+            }
         }
     }
+    send(AppMessage::TaskCompleted(format!("Demo '{}' finished.", path))).await
+}
 
-    fn scroll_to_top(&mut self) {
-        self.scroll_position = 0;
+/// Environment variable choosing the shell for `run`: `sh`, `cmd` or `powershell`.
+const SHELL_ENV: &str = "NEO_TERM_SHELL";
+
+/// The shell that `run <command>` hands command lines to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ShellKind {
+    /// `$SHELL -c`, falling back to `sh`.
+    Posix,
+    /// `cmd.exe /C`, the Windows default.
+    #[cfg(windows)]
+    Cmd,
+    PowerShell,
+}
+
+impl ShellKind {
+    fn from_env() -> Result<Self, String> {
+        match std::env::var(SHELL_ENV).unwrap_or_default().to_lowercase().as_str() {
+            "sh" => Ok(ShellKind::Posix),
+            #[cfg(windows)]
+            "cmd" => Ok(ShellKind::Cmd),
+            #[cfg(not(windows))]
+            "cmd" => Err(format!("{}=cmd is only available on Windows. Use sh or powershell.", SHELL_ENV)),
+            "powershell" | "pwsh" => Ok(ShellKind::PowerShell),
+            #[cfg(windows)]
+            _ => Ok(ShellKind::Cmd),
+            #[cfg(not(windows))]
+            _ => Ok(ShellKind::Posix),
+        }
     }
 
-    fn scroll_to_bottom(&mut self) {
-        self.scroll_position = self.lines.len().saturating_sub(self.max_lines);
+    #[cfg(not(windows))]
+    fn command(self, command_line: &str) -> tokio::process::Command {
+        let (program, args) = match self {
+            ShellKind::Posix => (std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string()), vec!["-c"]),
+            ShellKind::PowerShell => ("pwsh".to_string(), vec!["-NoLogo", "-NoProfile", "-NonInteractive", "-Command"]),
+        };
+        let mut command = tokio::process::Command::new(program);
+        command.args(args).arg(command_line);
+        command
     }
 
-    fn visible_lines(&self) -> &[String] {
-        let start = self.scroll_position;
-        let end = (start + self.max_lines).min(self.lines.len());
-        if start < self.lines.len() {
-            &self.lines[start..end]
-        } else {
-            &[]
+    /// The full Windows command line that starts `command_line` in this shell.
+    #[cfg(windows)]
+    fn windows_command_line(self, command_line: &str) -> String {
+        match self {
+            ShellKind::Posix => {
+                let shell = std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+                format!("{} -c {}", quote_windows_arg(&shell), quote_windows_arg(command_line))
+            }
+            ShellKind::Cmd => {
+                let comspec = std::env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string());
+                // cmd.exe does its own quote parsing, so the command line is passed verbatim.
+                // Code page 65001 keeps programs that print through the code page on UTF-8
+                format!("{} /D /S /C \"chcp 65001 >nul & {}\"", quote_windows_arg(&comspec), command_line)
+            }
+            ShellKind::PowerShell => {
+                let script = format!("[Console]::OutputEncoding = [System.Text.Encoding]::UTF8; {}", command_line);
+                format!("powershell -NoLogo -NoProfile -NonInteractive -Command {}", quote_windows_arg(&script))
+            }
         }
     }
+}
 
-    fn is_at_bottom(&self) -> bool {
-        self.scroll_position >= self.lines.len().saturating_sub(self.max_lines)
+/// Quotes one argument so `CommandLineToArgvW` (and the MSVC runtime) parses it back unchanged.
+#[cfg(any(windows, test))]
+fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Backslashes before a quote are doubled, and the quote itself escaped
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
     }
+    // Backslashes before the closing quote are doubled too
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
 }
 
-/// Holds the entire application state.
-struct AppState {
-    text_buffer: TextBuffer,
-    status_message: String,
-    message_receiver: mpsc::Receiver<AppMessage>,
-    command_input: String,
+/// Removes VT escape sequences (CSI, OSC and two-byte escapes) from console output,
+/// which ConPTY uses for colours, cursor movement and the window title.
+#[cfg(any(windows, test))]
+fn strip_ansi(bytes: &[u8]) -> Vec<u8> {
+    const ESC: u8 = 0x1b;
+    const BEL: u8 = 0x07;
+    let mut text = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != ESC {
+            text.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        match bytes.get(i + 1) {
+            Some(b'[') => {
+                // Parameters and intermediates run up to a final byte in 0x40..=0x7E
+                i += 2;
+                while i < bytes.len() && !(0x40..=0x7e).contains(&bytes[i]) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            Some(b']') => {
+                // Terminated by BEL or by ESC \
+                i += 2;
+                while i < bytes.len() && bytes[i] != BEL && !(bytes[i] == ESC && bytes.get(i + 1) == Some(&b'\\')) {
+                    i += 1;
+                }
+                i += if bytes.get(i) == Some(&ESC) { 2 } else { 1 };
+            }
+            Some(_) => i += 2,
+            None => i += 1,
+        }
+    }
+    text
 }
 
-/// Creates the "Hacker Theme" as specified in THEMING_SYSTEM.md.
-fn create_hacker_theme() -> Style {
-    let hacker_green = Color32::from_rgb(0, 255, 68);
-    let background_dark = Color32::from_rgb(10, 10, 10);
-    let mid_gray = Color32::from_rgb(60, 60, 60);
-    let light_gray = Color32::from_rgb(100, 100, 100);
-
-    let mut style = Style::default();
+/// Decodes one line of child process output. Strips the LF or CRLF terminator, keeps only
+/// the text after the last bare carriage return (progress output redraws a line that way)
+/// and replaces invalid UTF-8 instead of failing on it.
+fn decode_output_line(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_end_matches(['\n', '\r']);
+    text.rsplit('\r').next().unwrap_or_default().to_string()
+}
 
-    style.visuals = Visuals {
-        dark_mode: true,
-        override_text_color: Some(hacker_green),
-        panel_fill: background_dark,
-        window_rounding: Rounding::ZERO,
-        window_stroke: Stroke::new(1.0, mid_gray),
-        selection: egui::style::Selection {
-            bg_fill: Color32::from_rgba_premultiplied(
-                hacker_green.r(),
-                hacker_green.g(),
-                hacker_green.b(),
-                50,
-            ),
-            stroke: Stroke::new(1.0, hacker_green),
-        },
-        ..Visuals::dark()
-    };
+/// Forwards a child's output stream to the buffer line by line.
+#[cfg(not(windows))]
+async fn forward_output<R>(reader: Option<R>, tx: mpsc::Sender<AppMessage>, prefix: &str)
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncBufReadExt;
 
-    style.spacing = Spacing {
-        item_spacing: Vec2::new(8.0, 8.0),
-        ..Spacing::default()
+    let Some(reader) = reader else {
+        return;
     };
+    let mut reader = tokio::io::BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let text = format!("{}{}", prefix, decode_output_line(&line));
+                if tx.send(AppMessage::NewLine(text)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
 
-    style.text_styles = [
-        (
-            TextStyle::Heading,
-            FontId::new(24.0, FontFamily::Monospace),
-        ),
-        (TextStyle::Body, FontId::new(16.0, FontFamily::Monospace)),
-        (TextStyle::Button, FontId::new(16.0, FontFamily::Monospace)),
-        (
-            TextStyle::Monospace,
-            FontId::new(16.0, FontFamily::Monospace),
-        ),
-        (TextStyle::Small, FontId::new(12.0, FontFamily::Monospace)),
-    ]
-    .into();
+/// Runs a command line through `shell` without a terminal, streaming its output.
+#[cfg(not(windows))]
+async fn run_shell(tx: mpsc::Sender<AppMessage>, shell: ShellKind, command_line: String) -> Result<(), String> {
+    let mut child = shell
+        .command(&command_line)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start {:?} shell: {}", shell, e))?;
 
-    let widget_visuals = &mut style.visuals.widgets;
-    widget_visuals.inactive = egui::style::WidgetVisuals {
-        bg_fill: mid_gray,
-        fg_stroke: Stroke::new(1.0, hacker_green),
-        rounding: Rounding::ZERO,
-        bg_stroke: Stroke::new(1.0, hacker_green),
-        ..widget_visuals.inactive
-    };
-    widget_visuals.hovered = egui::style::WidgetVisuals {
-        bg_fill: light_gray,
-        fg_stroke: Stroke::new(2.0, hacker_green),
-        bg_stroke: Stroke::new(1.0, hacker_green),
-        ..widget_visuals.hovered
-    };
-    widget_visuals.active = egui::style::WidgetVisuals {
-        bg_fill: background_dark,
-        fg_stroke: Stroke::new(2.0, hacker_green),
-        bg_stroke: Stroke::new(2.0, hacker_green),
-        ..widget_visuals.active
-    };
+    let stdout = forward_output(child.stdout.take(), tx.clone(), "");
+    let stderr = forward_output(child.stderr.take(), tx.clone(), "[stderr] ");
+    let (status, _, _) = tokio::join!(child.wait(), stdout, stderr);
+    let status = status.map_err(|e| format!("Failed to wait for '{}': {}", command_line, e))?;
 
-    style
+    if !status.success() {
+        return Err(format!("'{}' exited with {}", command_line, status));
+    }
+    tx.send(AppMessage::TaskCompleted(format!("'{}' finished.", command_line)))
+        .await
+        .map_err(|_| "Failed to send command completion".to_string())
 }
 
-/// Main application struct that manages all resources
-struct NeoTermApp {
-    event_loop: Option<EventLoop<()>>,
-    window: Option<Arc<Window>>,
-    device: Option<wgpu::Device>,
-    queue: Option<wgpu::Queue>,
-    adapter: Option<wgpu::Adapter>,
-    config: Option<wgpu::SurfaceConfiguration>,
-    egui_ctx: Context,
-    egui_state: Option<EguiWinitState>,
-    egui_renderer: Option<EguiRenderer>,
-    app_state: AppState,
-    _message_sender: mpsc::Sender<AppMessage>, // Keep sender alive
+/// Runs a command line through `shell` inside a pseudo console, streaming its output.
+/// Programs see a real console, so stdout and stderr arrive interleaved as displayed.
+#[cfg(windows)]
+async fn run_shell(tx: mpsc::Sender<AppMessage>, shell: ShellKind, command_line: String) -> Result<(), String> {
+    let windows_command_line = shell.windows_command_line(&command_line);
+    let output = tx.clone();
+    let exit_code = tokio::task::spawn_blocking(move || {
+        conpty::run(&windows_command_line, move |line| {
+            let text = decode_output_line(&strip_ansi(line));
+            // The pseudo console repaints blank rows, which carry nothing for the buffer
+            if !text.trim().is_empty() {
+                let _ = output.blocking_send(AppMessage::NewLine(text));
+            }
+        })
+    })
+    .await
+    .map_err(|e| format!("Pseudo console task failed: {}", e))?
+    .map_err(|e| format!("Failed to start {:?} shell: {}", shell, e))?;
+
+    if exit_code != 0 {
+        return Err(format!("'{}' exited with code {}", command_line, exit_code));
+    }
+    tx.send(AppMessage::TaskCompleted(format!("'{}' finished.", command_line)))
+        .await
+        .map_err(|_| "Failed to send command completion".to_string())
 }
 
-impl NeoTermApp {
-    async fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        env_logger::init();
+/// Windows pseudo console (ConPTY, Windows 10 1809 and later).
+#[cfg(windows)]
+mod conpty {
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io::{self, BufRead, BufReader};
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use std::ptr::{null, null_mut};
+    use windows_sys::Win32::Foundation::{BOOL, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Console::{ClosePseudoConsole, CreatePseudoConsole, COORD, HPCON};
+    use windows_sys::Win32::System::Pipes::CreatePipe;
+    use windows_sys::Win32::System::Threading::{
+        CreateProcessW, DeleteProcThreadAttributeList, GetExitCodeProcess, InitializeProcThreadAttributeList,
+        UpdateProcThreadAttribute, WaitForSingleObject, EXTENDED_STARTUPINFO_PRESENT, INFINITE,
+        PROCESS_INFORMATION, PROC_THREAD_ATTRIBUTE_PSEUDOCONSOLE, STARTF_USESTDHANDLES, STARTUPINFOEXW,
+    };
 
-        let event_loop = EventLoop::new()?;
-        let window = Arc::new(Window::new(&event_loop)?);
-        window.set_title("Neo-Term");
+    /// Size of the pseudo console screen, in character cells.
+    const COLUMNS: i16 = 120;
+    const ROWS: i16 = 30;
 
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
-            .await
-            .ok_or("Failed to find suitable adapter")?;
+    /// Closes the pseudo console on drop, which also ends its output pipe.
+    struct PseudoConsole(HPCON);
 
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor::default(), None)
-            .await?;
+    impl Drop for PseudoConsole {
+        fn drop(&mut self) {
+            unsafe { ClosePseudoConsole(self.0) }
+        }
+    }
 
-        let size = window.inner_size();
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb, // Default format
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
+    /// Owns an initialized attribute list and the memory backing it.
+    struct AttributeList {
+        // usize elements keep the list pointer-aligned
+        _memory: Vec<usize>,
+        list: *mut c_void,
+    }
+
+    impl Drop for AttributeList {
+        fn drop(&mut self) {
+            unsafe { DeleteProcThreadAttributeList(self.list) }
+        }
+    }
+
+    fn check(result: BOOL) -> io::Result<()> {
+        if result == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Creates an anonymous pipe, returning its (read, write) ends.
+    fn pipe() -> io::Result<(OwnedHandle, OwnedHandle)> {
+        let (mut read, mut write): (HANDLE, HANDLE) = (null_mut(), null_mut());
+        unsafe {
+            check(CreatePipe(&mut read, &mut write, null(), 0))?;
+            Ok((OwnedHandle::from_raw_handle(read), OwnedHandle::from_raw_handle(write)))
+        }
+    }
+
+    /// Runs `command_line` in a new pseudo console, calling `on_line` with each line it
+    /// prints (VT sequences included) from a reader thread. Blocks until the process
+    /// exits and returns its exit code.
+    pub fn run<F>(command_line: &str, mut on_line: F) -> io::Result<u32>
+    where
+        F: FnMut(&[u8]) + Send + 'static,
+    {
+        let (console_input, input) = pipe()?;
+        let (output, console_output) = pipe()?;
+
+        let mut console: HPCON = unsafe { std::mem::zeroed() };
+        let result = unsafe {
+            CreatePseudoConsole(
+                COORD { X: COLUMNS, Y: ROWS },
+                console_input.as_raw_handle(),
+                console_output.as_raw_handle(),
+                0,
+                &mut console,
+            )
         };
+        if result < 0 {
+            return Err(io::Error::other(format!("CreatePseudoConsole failed with HRESULT {:#010x}", result)));
+        }
+        let console = PseudoConsole(console);
+        // The pseudo console keeps its own duplicates of its ends of the pipes
+        drop(console_input);
+        drop(console_output);
 
-        let egui_ctx = Context::default();
-        let egui_state = EguiWinitState::new(egui_ctx.clone(), ViewportId::ROOT, &event_loop, None, None);
-        let egui_renderer = EguiRenderer::new(&device, wgpu::TextureFormat::Bgra8UnormSrgb, None, 1);
-        egui_ctx.set_style(create_hacker_theme());
+        let attributes = unsafe {
+            let mut size = 0;
+            // The first call only reports the size needed, so its failure is expected
+            InitializeProcThreadAttributeList(null_mut(), 1, 0, &mut size);
+            let mut memory = vec![0usize; size.div_ceil(std::mem::size_of::<usize>())];
+            let list = memory.as_mut_ptr() as *mut c_void;
+            check(InitializeProcThreadAttributeList(list, 1, 0, &mut size))?;
+            let attributes = AttributeList { _memory: memory, list };
+            check(UpdateProcThreadAttribute(
+                attributes.list,
+                0,
+                PROC_THREAD_ATTRIBUTE_PSEUDOCONSOLE as usize,
+                console.0 as *const c_void,
+                std::mem::size_of::<HPCON>(),
+                null_mut(),
+                null_mut(),
+            ))?;
+            attributes
+        };
 
-        let (message_sender, message_receiver) = mpsc::channel::<AppMessage>(1000);
+        let mut startup: STARTUPINFOEXW = unsafe { std::mem::zeroed() };
+        startup.StartupInfo.cb = std::mem::size_of::<STARTUPINFOEXW>() as u32;
+        // Invalid standard handles make the child use the pseudo console instead of
+        // inheriting whatever this GUI process has as stdio
+        startup.StartupInfo.dwFlags = STARTF_USESTDHANDLES;
+        startup.StartupInfo.hStdInput = INVALID_HANDLE_VALUE;
+        startup.StartupInfo.hStdOutput = INVALID_HANDLE_VALUE;
+        startup.StartupInfo.hStdError = INVALID_HANDLE_VALUE;
+        startup.lpAttributeList = attributes.list;
 
-        let mut app_state = AppState {
-            text_buffer: TextBuffer::new(1000),
-            status_message: "STATUS: System nominal.".to_string(),
-            message_receiver,
-            command_input: String::new(),
+        let mut command_line: Vec<u16> = command_line.encode_utf16().chain(Some(0)).collect();
+        let mut information: PROCESS_INFORMATION = unsafe { std::mem::zeroed() };
+        unsafe {
+            check(CreateProcessW(
+                null(),
+                command_line.as_mut_ptr(),
+                null(),
+                null(),
+                0,
+                EXTENDED_STARTUPINFO_PRESENT,
+                null(),
+                null(),
+                &startup.StartupInfo,
+                &mut information,
+            ))?;
+        }
+        let (process, _thread) = unsafe {
+            (OwnedHandle::from_raw_handle(information.hProcess), OwnedHandle::from_raw_handle(information.hThread))
         };
 
-        // Initialize with ASCII art
-        let ascii_art = [
-            "███╗   ██╗███████╗ ██████╗",
-            "████╗  ██║██╔════╝██╔═══██╗",
-            "██╔██╗ ██║█████╗  ██║   ██║",
-            "██║╚██╗██║██╔══╝  ██║   ██║",
-            "██║ ╚████║███████╗╚██████╔╝",
-            "╚═╝  ╚═══╝╚══════╝ ╚═════╝ ",
-            "",
-            "Welcome to Neo-Term. Standby for commands."
-        ];
-        for line in &ascii_art {
-            app_state.text_buffer.add_line(line.to_string());
+        // The console must be drained while the process runs or it stops writing
+        let reader = std::thread::spawn(move || {
+            let mut reader = BufReader::new(File::from(output));
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) => break,
+                    Ok(_) => on_line(&line),
+                    Err(_) => {
+                        if !line.is_empty() {
+                            on_line(&line);
+                        }
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut exit_code = 0;
+        unsafe {
+            WaitForSingleObject(process.as_raw_handle(), INFINITE);
+            check(GetExitCodeProcess(process.as_raw_handle(), &mut exit_code))?;
         }
 
-        Ok(Self {
-            event_loop: Some(event_loop),
-            window: Some(window),
-            device: Some(device),
-            queue: Some(queue),
-            adapter: Some(adapter),
-            config: Some(config),
-            egui_ctx,
-            egui_state: Some(egui_state),
-            egui_renderer: Some(egui_renderer),
-            app_state,
-            _message_sender: message_sender,
-        })
+        // Closing the console flushes its last output and breaks the pipe, ending the reader
+        drop(attributes);
+        drop(console);
+        drop(input);
+        let _ = reader.join();
+        Ok(exit_code)
     }
+}
 
-    fn run(mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let event_loop = self.event_loop.take().unwrap();
-        let window = self.window.take().unwrap();
-        let device = self.device.take().unwrap();
-        let queue = self.queue.take().unwrap();
-        let config = self.config.take().unwrap();
-        let mut egui_state = self.egui_state.take().unwrap();
-        let mut egui_renderer = self.egui_renderer.take().unwrap();
-
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
-        let surface = instance.create_surface(window.clone())?;
-        surface.configure(&device, &config);
+/// Environment variable overriding the location of the profiles file.
+const PROFILES_ENV: &str = "NEO_TERM_PROFILES";
 
-        event_loop.run(move |event, elwt| {
-            elwt.set_control_flow(ControlFlow::Poll);
+/// How long a `check host` waits for a TCP connection.
+const HOST_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
 
-            match event {
-                Event::WindowEvent { window_id, event } if window_id == window.id() => {
-                    let response = egui_state.on_window_event(&window, &event);
-                    if response.consumed {
-                        return;
-                    }
+/// A startup health check declared by a profile.
+#[derive(Debug, Clone, PartialEq)]
+enum HealthCheck {
+    /// `host <host:port>`: a TCP connection can be opened.
+    Host(String),
+    /// `path <path>`: the path exists, e.g. a mounted share.
+    Path(String),
+    /// `env <NAME>`: the environment variable is set.
+    Env(String),
+}
 
-                    match event {
-                        WindowEvent::CloseRequested => elwt.exit(),
-                        WindowEvent::Resized(new_size) => {
-                            let mut config = config.clone();
-                            config.width = new_size.width.max(1);
-                            config.height = new_size.height.max(1);
-                            surface.configure(&device, &config);
-                            // Store updated config back
-                            drop(config);
-                        }
-                        WindowEvent::RedrawRequested => {
-                            // Process all available messages
-                            while let Ok(message) = self.app_state.message_receiver.try_recv() {
-                                match message {
-                                    AppMessage::TaskCompleted(result) => {
-                                        self.app_state.status_message = format!("STATUS: {}", result);
-                                        self.app_state.text_buffer.add_line(format!("[ASYNC] {}", result));
-                                    }
-                                    AppMessage::NewLine(line) => self.app_state.text_buffer.add_line(line),
-                                }
-                            }
-
-                            let raw_input = egui_state.take_egui_input(&window);
-                            let output = self.egui_ctx.run(raw_input, |ctx| {
-                                draw_ui(ctx, &mut self.app_state, mpsc::Sender::clone(&self._message_sender));
-                            });
-
-                            egui_state.handle_platform_output(&window, output.platform_output);
+impl HealthCheck {
+    fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once(' ').map(|(kind, target)| (kind, target.trim())) {
+            Some(("host", target)) => Ok(HealthCheck::Host(target.to_string())),
+            Some(("path", target)) => Ok(HealthCheck::Path(target.to_string())),
+            Some(("env", target)) => Ok(HealthCheck::Env(target.to_string())),
+            _ => Err(format!("invalid check '{}', expected host, path or env", spec)),
+        }
+    }
 
-                            let screen_descriptor = ScreenDescriptor {
-                                size_in_pixels: [config.width, config.height],
-                                pixels_per_point: window.scale_factor() as f32,
-                            };
-                            let paint_jobs = self.egui_ctx.tessellate(output.shapes, screen_descriptor.pixels_per_point);
+    fn describe(&self) -> String {
+        match self {
+            HealthCheck::Host(host) => format!("host {} reachable", host),
+            HealthCheck::Path(path) => format!("path {} present", path),
+            HealthCheck::Env(name) => format!("env {} set", name),
+        }
+    }
 
-                            let frame = match surface.get_current_texture() {
-                                Ok(frame) => frame,
-                                Err(e) => {
-                                    eprintln!("Failed to get surface texture: {:?}", e);
-                                    return;
-                                }
-                            };
-                            let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
-                            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
-
-                            egui_renderer.update_buffers(&device, &queue, &mut encoder, &paint_jobs, &screen_descriptor);
-
-                            let clear_color = wgpu::Color { r: 10.0/255.0, g: 10.0/255.0, b: 10.0/255.0, a: 1.0 };
-                            {
-                                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                                    label: None,
-                                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                                        view: &view,
-                                        resolve_target: None,
-                                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(clear_color), store: wgpu::StoreOp::Store },
-                                    })],
-                                    depth_stencil_attachment: None,
-                                    timestamp_writes: None,
-                                    occlusion_query_set: None,
-                                });
-                                egui_renderer.render(&mut render_pass, &paint_jobs, &screen_descriptor);
-                            }
-                            queue.submit(Some(encoder.finish()));
-                            frame.present();
-                        }
-                        _ => {}
-                    }
-                }
-                Event::AboutToWait => {
-                    // Check if we have new messages to process
-                    if self.app_state.message_receiver.try_recv().is_ok() {
-                        window.request_redraw();
-                    } else {
-                        // Throttle redraws to reduce CPU usage
-                        elwt.set_control_flow(ControlFlow::Wait);
-                    }
+    /// Runs the check, returning why it did not pass.
+    async fn run(&self) -> Result<(), String> {
+        match self {
+            HealthCheck::Host(host) => {
+                match tokio::time::timeout(HOST_CHECK_TIMEOUT, tokio::net::TcpStream::connect(host.as_str())).await {
+                    Ok(Ok(_)) => Ok(()),
+                    Ok(Err(e)) => Err(e.to_string()),
+                    Err(_) => Err(format!("no answer within {}s", HOST_CHECK_TIMEOUT.as_secs())),
                 }
-                _ => (),
             }
-        })?;
+            HealthCheck::Path(path) => tokio::fs::metadata(path).await.map(|_| ()).map_err(|e| e.to_string()),
+            HealthCheck::Env(name) => match std::env::var_os(name) {
+                Some(_) => Ok(()),
+                None => Err("not set".to_string()),
+            },
+        }
+    }
+}
 
-        // Resources will be automatically cleaned up when the struct is dropped
-        Ok(())
+/// A named setup: health checks that must pass before its startup command runs.
+#[derive(Debug, Clone)]
+struct Profile {
+    name: String,
+    startup: Option<String>,
+    checks: Vec<HealthCheck>,
+}
+
+/// Parses the profiles file, an INI-style list of `[name]` sections with
+/// `startup = <command>` and any number of `check = <kind> <target>` entries.
+fn parse_profiles(text: &str) -> Result<Vec<Profile>, String> {
+    let mut profiles: Vec<Profile> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            profiles.push(Profile {
+                name: name.trim().to_string(),
+                startup: None,
+                checks: Vec::new(),
+            });
+            continue;
+        }
+
+        let error = |message: String| format!("line {}: {}", number + 1, message);
+        let profile = profiles.last_mut().ok_or_else(|| error("entry outside of a [profile] section".to_string()))?;
+        match line.split_once('=').map(|(key, value)| (key.trim(), value.trim())) {
+            Some(("startup", command)) => profile.startup = Some(command.to_string()),
+            Some(("check", check)) => profile.checks.push(HealthCheck::parse(check).map_err(error)?),
+            _ => return Err(error(format!("unknown entry '{}'", line))),
+        }
     }
+    Ok(profiles)
 }
 
-impl Drop for NeoTermApp {
-    fn drop(&mut self) {
-        println!("NeoTermApp: Cleaning up resources...");
-        // All resources are automatically cleaned up when Arc and Box are dropped
-        // Explicit cleanup is handled by the respective libraries
+fn profiles_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(PROFILES_ENV) {
+        return Some(PathBuf::from(path));
     }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".config").join("neo-term").join("profiles.conf"))
 }
 
-fn process_command(command: &str, state: &mut AppState, sender: mpsc::Sender<AppMessage>) {
-    let cmd = command.trim().to_lowercase();
+/// Loads a profile, prints its health checklist and runs the startup command if every check passed.
+async fn open_profile(tx: mpsc::Sender<AppMessage>, name: String) -> Result<(), String> {
+    let path = profiles_path().ok_or("No profiles file: set NEO_TERM_PROFILES or HOME")?;
+    let text = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let profile = parse_profiles(&text)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| format!("No profile named '{}' in {}", name, path.display()))?;
 
-    match cmd.as_str() {
-        "help" | "?" => {
-            let help_text = [
-                "Available commands:",
-                "  help, ?          - Show this help message",
-                "  clear            - Clear the terminal",
-                "  status           - Show system status",
-                "  echo <text>      - Echo text back",
-                "  time             - Show current time",
-                "  date             - Show current date",
-                "  async-task       - Run async task",
-                "  log              - Generate log entry",
-                "  scroll-top       - Scroll to top",
-                "  scroll-bottom    - Scroll to bottom",
-            ];
-            for line in &help_text {
-                state.text_buffer.add_line(line.to_string());
+    let send = |message: AppMessage| async {
+        tx.send(message).await.map_err(|_| "Failed to send profile output".to_string())
+    };
+
+    send(AppMessage::NewLine(format!("[PROFILE] {}: {} startup check(s)", profile.name, profile.checks.len()))).await?;
+    let mut failed = 0;
+    for check in &profile.checks {
+        let line = match check.run().await {
+            Ok(()) => format!("  [PASS] {}", check.describe()),
+            Err(reason) => {
+                failed += 1;
+                format!("  [FAIL] {}: {}", check.describe(), reason)
             }
+        };
+        send(AppMessage::NewLine(line)).await?;
+    }
+
+    match profile.startup {
+        Some(startup) if failed == 0 => send(AppMessage::RunCommand(startup)).await,
+        Some(_) => send(AppMessage::NewLine(format!("[PROFILE] Startup command skipped: {} check(s) did not pass.", failed))).await,
+        None => Ok(()),
+    }
+}
+
+/// How long `fetch` waits for a TCP connection.
+const FETCH_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Redirects `fetch` follows before giving up.
+const FETCH_MAX_REDIRECTS: usize = 5;
+
+/// Minimum time between two progress updates from a download.
+const FETCH_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Arguments of `fetch <url> [-o path] [--sha256 <hash>]`.
+#[derive(Debug)]
+struct FetchRequest {
+    url: String,
+    output: PathBuf,
+    /// Expected digest as lowercase hex.
+    sha256: Option<String>,
+}
+
+fn parse_fetch_args(args: &str) -> Result<FetchRequest, String> {
+    let mut url = None;
+    let mut output = None;
+    let mut sha256 = None;
+    let mut words = args.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "-o" => output = Some(PathBuf::from(words.next().ok_or("-o needs a path")?)),
+            "--sha256" => {
+                let hash = words.next().ok_or("--sha256 needs a hash")?.to_ascii_lowercase();
+                if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err(format!("'{}' is not a SHA-256 hash (64 hex digits)", hash));
+                }
+                sha256 = Some(hash);
+            }
+            _ if url.is_none() => url = Some(word.to_string()),
+            other => return Err(format!("Unexpected argument '{}'", other)),
         }
-        "clear" => {
-            state.text_buffer = TextBuffer::new(1000);
-            state.text_buffer.add_line("Terminal cleared.".to_string());
-        }
-        "status" => {
-            state.text_buffer.add_line(format!("System Status: {}", state.status_message));
-        }
-        cmd if cmd.starts_with("echo ") => {
-            let echo_text = &cmd[5..];
-            state.text_buffer.add_line(echo_text.to_string());
+    }
+
+    let url = url.ok_or("Usage: fetch <url> [-o path] [--sha256 <hash>]")?;
+    // Default to the last path segment, like curl -O
+    let output = output.unwrap_or_else(|| {
+        let after_scheme = url.split_once("://").map_or(url.as_str(), |(_, rest)| rest);
+        let path = after_scheme.split(['?', '#']).next().unwrap_or_default();
+        match path.split_once('/').and_then(|(_, path)| path.rsplit('/').next()) {
+            Some(name) if !name.is_empty() => PathBuf::from(name),
+            _ => PathBuf::from("index.html"),
         }
-        "time" => {
-            let time = chrono::Local::now().format("%H:%M:%S");
-            state.text_buffer.add_line(format!("Current time: {}", time));
+    });
+    Ok(FetchRequest { url, output, sha256 })
+}
+
+/// An `http://` URL split into what a request needs.
+#[derive(Debug)]
+struct HttpUrl {
+    /// `host[:port]`, as sent in the `Host` header.
+    authority: String,
+    host: String,
+    port: u16,
+    /// Path and query, starting with `/`.
+    path: String,
+}
+
+fn parse_http_url(url: &str) -> Result<HttpUrl, String> {
+    if url.starts_with("https://") {
+        return Err("https:// is not supported yet (no TLS support); use an http:// URL".to_string());
+    }
+    let rest = url.strip_prefix("http://").ok_or_else(|| format!("'{}' is not an http:// URL", url))?;
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(index) if rest[index..].starts_with('?') => (&rest[..index], format!("/{}", &rest[index..])),
+        Some(index) => (&rest[..index], rest[index..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        // A colon inside brackets belongs to an IPv6 address, not a port
+        Some((host, port)) if !port.contains(']') => {
+            (host, port.parse().map_err(|_| format!("invalid port '{}'", port))?)
         }
-        "date" => {
-            let date = chrono::Local::now().format("%Y-%m-%d");
-            state.text_buffer.add_line(format!("Current date: {}", date));
+        _ => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("'{}' has no host", url));
+    }
+    Ok(HttpUrl {
+        authority: authority.to_string(),
+        host: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+        port,
+        path,
+    })
+}
+
+/// Resolves a redirect `Location` against the URL that returned it.
+fn resolve_location(base: &HttpUrl, location: &str) -> String {
+    if location.contains("://") {
+        location.to_string()
+    } else if location.starts_with('/') {
+        format!("http://{}{}", base.authority, location)
+    } else {
+        let directory = &base.path[..base.path.rfind('/').map_or(0, |index| index + 1)];
+        format!("http://{}{}{}", base.authority, directory, location)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Minimal streaming SHA-256 (FIPS 180-4) for verifying downloads.
+struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Sha256 {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ];
+
+    fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+            ],
+            block: [0; 64],
+            block_len: 0,
+            total_len: 0,
         }
-        "async-task" => {
-            let tx = sender.clone();
-            tokio::spawn(async move {
-                if tx.send(AppMessage::NewLine("[COMMAND] Async task started...".to_string())).await.is_err() {
-                    eprintln!("Failed to send command response");
-                    return;
-                }
-                tokio::time::sleep(Duration::from_secs(1)).await;
-                if tx.send(AppMessage::TaskCompleted("Command executed successfully.".to_string())).await.is_err() {
-                    eprintln!("Failed to send command completion");
-                }
-            });
-            state.text_buffer.add_line("Async task initiated.".to_string());
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + take].copy_from_slice(&data[..take]);
+            self.block_len += take;
+            data = &data[take..];
+            if self.block_len == 64 {
+                self.compress();
+                self.block_len = 0;
+            }
         }
-        "log" => {
-            let tx = sender.clone();
-            tokio::spawn(async move {
-                if tx.send(AppMessage::NewLine(format!("[COMMAND] Log entry at {}", chrono::Local::now().format("%H:%M:%S")))).await.is_err() {
-                    eprintln!("Failed to send log message");
-                }
-            });
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, word) in self.block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
         }
-        "scroll-top" => {
-            state.text_buffer.scroll_to_top();
-            state.text_buffer.add_line("Scrolled to top.".to_string());
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
         }
-        "scroll-bottom" => {
-            state.text_buffer.scroll_to_bottom();
-            state.text_buffer.add_line("Scrolled to bottom.".to_string());
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (k, w) in Self::K.iter().zip(w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(*k).wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
         }
-        "" => {
-            // Empty command, do nothing
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
         }
-        _ => {
-            state.text_buffer.add_line(format!("Unknown command: '{}'. Type 'help' for available commands.", cmd));
+    }
+
+    /// Pads the message and returns the digest as lowercase hex.
+    fn finish_hex(mut self) -> String {
+        let bit_len = self.total_len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
         }
+        self.update(&bit_len.to_be_bytes());
+        self.state.iter().map(|word| format!("{:08x}", word)).collect()
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let app = NeoTermApp::new().await?;
-    app.run()
+/// Writes a download to disk while hashing it and publishing progress.
+struct Download {
+    file: tokio::fs::File,
+    hasher: Sha256,
+    received: u64,
+    total: Option<u64>,
+    started: Instant,
+    last_report: Instant,
+    progress: ProgressReporter,
 }
 
-fn draw_ui(ctx: &Context, state: &mut AppState, sender: mpsc::Sender<AppMessage>) {
-    egui::CentralPanel::default().show(ctx, |ui| {
-        ui.heading("SYSTEM CONSOLE");
-        ui.separator();
+impl Download {
+    async fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        use tokio::io::AsyncWriteExt;
+        self.file.write_all(data).await.map_err(|e| format!("Failed to write download: {}", e))?;
+        self.hasher.update(data);
+        self.received += data.len() as u64;
+        if self.last_report.elapsed() >= FETCH_PROGRESS_INTERVAL {
+            self.last_report = Instant::now();
+            self.report();
+        }
+        Ok(())
+    }
 
-        let text_frame = egui::Frame::dark_canvas(ui.style());
-        text_frame.show(ui, |ui| {
-             ScrollArea::vertical()
-                .auto_shrink([false, false])
-                .stick_to_bottom(!state.text_buffer.is_at_bottom())
-                .show(ui, |ui| {
-                    ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
-                        for line in state.text_buffer.visible_lines() {
-                            ui.label(line);
-                        }
-                    });
-                    ui.allocate_space(ui.available_size());
-                });
-        });
+    /// Flushes the file and waits until its contents are on disk.
+    async fn sync(&mut self) -> Result<(), String> {
+        use tokio::io::AsyncWriteExt;
+        self.file.flush().await.map_err(|e| format!("Failed to write download: {}", e))?;
+        self.file.sync_all().await.map_err(|e| format!("Failed to save download: {}", e))
+    }
 
-        ui.add_space(8.0);
+    fn speed(&self) -> String {
+        let seconds = self.started.elapsed().as_secs_f64().max(0.001);
+        format!("{}/s", format_bytes((self.received as f64 / seconds) as u64))
+    }
 
-        ui.vertical(|ui| {
-            ui.heading("ASYNC_TASK_MODULE");
-            if ui.button("> EXECUTE_SLOW_TASK (2 seconds)").clicked() {
-                let tx = sender.clone();
-                tokio::spawn(async move {
-                    if tx.send(AppMessage::NewLine("[ASYNC] Task started...".to_string())).await.is_err() {
-                        eprintln!("Failed to send async task start message");
-                        return;
-                    }
-                    tokio::time::sleep(Duration::from_secs(2)).await;
-                    if tx.send(AppMessage::TaskCompleted("Task completed successfully.".to_string())).await.is_err() {
-                        eprintln!("Failed to send task completion message");
-                    }
-                });
+    fn report(&self) {
+        let (fraction, size) = match self.total {
+            Some(total) if total > 0 => (
+                Some(self.received as f32 / total as f32),
+                format!("{} / {}", format_bytes(self.received), format_bytes(total)),
+            ),
+            _ => (None, format_bytes(self.received)),
+        };
+        self.progress.report(fraction, format!("{}, {}", size, self.speed()));
+    }
+}
+
+/// Sends a GET request, following redirects, and returns the response
+/// positioned at the body together with its lowercased headers.
+async fn http_get(url: &str) -> Result<(tokio::io::BufReader<tokio::net::TcpStream>, Vec<(String, String)>), String> {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let mut url = url.to_string();
+    for _ in 0..=FETCH_MAX_REDIRECTS {
+        let target = parse_http_url(&url)?;
+        let mut stream = match tokio::time::timeout(FETCH_CONNECT_TIMEOUT, tokio::net::TcpStream::connect((target.host.as_str(), target.port))).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => return Err(format!("Failed to connect to {}: {}", target.authority, e)),
+            Err(_) => return Err(format!("Timed out connecting to {}", target.authority)),
+        };
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: neo-term/{}\r\nAccept-Encoding: identity\r\nConnection: close\r\n\r\n",
+            target.path,
+            target.authority,
+            env!("CARGO_PKG_VERSION")
+        );
+        stream.write_all(request.as_bytes()).await.map_err(|e| format!("Failed to send request: {}", e))?;
+
+        let mut reader = tokio::io::BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).await.map_err(|e| format!("Failed to read response: {}", e))?;
+        let status: u16 = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| format!("Invalid HTTP response '{}'", status_line.trim()))?;
+
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.map_err(|e| format!("Failed to read headers: {}", e))?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
             }
-            if ui.button("> GENERATE LOG LINE").clicked() {
-                let tx = sender.clone();
-                tokio::spawn(async move {
-                    if tx.send(AppMessage::NewLine(format!("[LOG] Sample log entry at {}", chrono::Local::now().format("%H:%M:%S")))).await.is_err() {
-                        eprintln!("Failed to send log message");
-                    }
-                });
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
             }
-        });
-        ui.add_space(8.0);
+        }
 
-        // Command input section
-        ui.horizontal(|ui| {
-            ui.heading("COMMAND INPUT");
-            ui.add_space(8.0);
-        });
+        let location = headers.iter().find(|(name, _)| name == "location").map(|(_, value)| value.clone());
+        match (status, location) {
+            (200..=299, _) => return Ok((reader, headers)),
+            (301 | 302 | 303 | 307 | 308, Some(location)) => url = resolve_location(&target, &location),
+            _ => return Err(format!("Server answered '{}'", status_line.trim())),
+        }
+    }
+    Err(format!("Stopped after {} redirects", FETCH_MAX_REDIRECTS))
+}
 
-        ui.horizontal(|ui| {
-            ui.label(">");
-            let response = ui.text_edit_singleline(&mut state.command_input);
-            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
-                if !state.command_input.trim().is_empty() {
-                    let command = state.command_input.clone();
-                    state.text_buffer.add_line(format!("> {}", command));
-                    process_command(&command, state, sender.clone());
-                    state.command_input.clear();
+/// Downloads `request.url` to `request.output`, verifying the SHA-256 if one was given.
+/// The file is written next to the target as `.part` and only renamed once complete and verified.
+async fn fetch(tx: mpsc::Sender<AppMessage>, progress: ProgressReporter, request: FetchRequest) -> Result<(), String> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    let (mut reader, headers) = http_get(&request.url).await?;
+    let header = |wanted: &str| headers.iter().find(|(name, _)| name == wanted).map(|(_, value)| value.as_str());
+    let chunked = header("transfer-encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+    let total = header("content-length").and_then(|value| value.parse().ok());
+
+    let mut part_name = request.output.clone().into_os_string();
+    part_name.push(".part");
+    let part_path = PathBuf::from(part_name);
+    let file = tokio::fs::File::create(&part_path)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", part_path.display(), e))?;
+    let mut download = Download {
+        file,
+        hasher: Sha256::new(),
+        received: 0,
+        total: if chunked { None } else { total },
+        started: Instant::now(),
+        last_report: Instant::now(),
+        progress,
+    };
+    download.report();
+
+    let read_error = |e: std::io::Error| format!("Download interrupted: {}", e);
+    let mut buffer = vec![0u8; 64 * 1024];
+    let body: Result<(), String> = async {
+        if chunked {
+            loop {
+                let mut size_line = String::new();
+                reader.read_line(&mut size_line).await.map_err(read_error)?;
+                let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+                let mut remaining = u64::from_str_radix(size_hex, 16).map_err(|_| format!("Invalid chunk size '{}'", size_hex))?;
+                if remaining == 0 {
+                    break;
                 }
-            }
-            if ui.button("Execute").clicked() {
-                if !state.command_input.trim().is_empty() {
-                    let command = state.command_input.clone();
-                    state.text_buffer.add_line(format!("> {}", command));
-                    process_command(&command, state, sender.clone());
-                    state.command_input.clear();
+                while remaining > 0 {
+                    let want = buffer.len().min(remaining as usize);
+                    let read = reader.read(&mut buffer[..want]).await.map_err(read_error)?;
+                    if read == 0 {
+                        return Err("Connection closed in the middle of a chunk".to_string());
+                    }
+                    download.write(&buffer[..read]).await?;
+                    remaining -= read as u64;
                 }
+                // Each chunk ends with CRLF
+                let mut line_end = String::new();
+                reader.read_line(&mut line_end).await.map_err(read_error)?;
             }
-        });
-
-        // Scroll controls
-        ui.horizontal(|ui| {
-            ui.heading("SCROLL CONTROLS");
-            if ui.button("↑ Top").clicked() {
-                state.text_buffer.scroll_to_top();
-            }
-            if ui.button("↑ Up").clicked() {
-                state.text_buffer.scroll_up();
-            }
-            if ui.button("↓ Down").clicked() {
-                state.text_buffer.scroll_down();
+        } else {
+            loop {
+                let read = reader.read(&mut buffer).await.map_err(read_error)?;
+                if read == 0 {
+                    break;
+                }
+                download.write(&buffer[..read]).await?;
             }
-            if ui.button("↓ Bottom").clicked() {
-                state.text_buffer.scroll_to_bottom();
+            if let Some(total) = total {
+                if download.received < total {
+                    return Err(format!("Connection closed after {} of {}", format_bytes(download.received), format_bytes(total)));
+                }
             }
+        }
+        Ok(())
+    }
+    .await;
+    // The data has to be on disk before the file is renamed into place and reported as saved
+    let body = match body {
+        Ok(()) => download.sync().await,
+        Err(e) => Err(e),
+    };
 
-            let total_lines = state.text_buffer.lines.len();
-            let visible_lines = state.text_buffer.max_lines;
-            let scroll_pos = state.text_buffer.scroll_position;
+    let elapsed = download.started.elapsed();
+    let speed = download.speed();
+    let Download { file, hasher, received, .. } = download;
+    drop(file);
+    let digest = hasher.finish_hex();
+    let verified = match (&body, &request.sha256) {
+        (Ok(()), Some(expected)) if *expected != digest => Err(format!("sha256 mismatch: expected {}, got {}", expected, digest)),
+        _ => body,
+    };
+    if let Err(e) = verified {
+        let _ = tokio::fs::remove_file(&part_path).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&part_path, &request.output)
+        .await
+        .map_err(|e| format!("Failed to move download to {}: {}", request.output.display(), e))?;
 
-            if total_lines > visible_lines {
-                let percentage = if total_lines > 0 {
-                    ((scroll_pos as f32) / (total_lines - visible_lines) as f32 * 100.0) as usize
-                } else {
-                    0
-                };
-                ui.label(format!("Position: {}% ({}/{})",
-                    percentage, scroll_pos + 1, total_lines));
-            } else {
-                ui.label(format!("Lines: {}/{}", total_lines, visible_lines));
-            }
-        });
+    let checksum = match request.sha256 {
+        Some(_) => format!("[FETCH] sha256 {} verified", digest),
+        None => format!("[FETCH] sha256 {}", digest),
+    };
+    for line in [
+        format!("[FETCH] Saved {} to {} in {:.1}s ({})", format_bytes(received), request.output.display(), elapsed.as_secs_f64(), speed),
+        checksum,
+    ] {
+        tx.send(AppMessage::NewLine(line)).await.map_err(|_| "Failed to send fetch result".to_string())?;
+    }
+    Ok(())
+}
 
-        ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-            ui.separator();
-            ui.label(&state.status_message);
-        });
-    });
-}
\ No newline at end of file
+/// Longest side, in pixels, of an image attached to the AI panel. Larger screenshots are
+/// scaled down locally, since multimodal models downscale them anyway.
+const MAX_IMAGE_SIDE: u32 = 1568;
+
+/// Largest encoded attachment; images over it are scaled down further. 3 MiB stays
+/// under 5 MB once base64 encoded into a request.
+const MAX_IMAGE_BYTES: usize = 3 * 1024 * 1024;
+
+/// Largest image `decode_png` accepts, in pixels, so a bogus header cannot exhaust memory.
+const MAX_DECODED_PIXELS: u64 = 64 * 1024 * 1024;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// An 8-bit RGBA image.
+#[derive(Debug, Clone, PartialEq)]
+struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// A clipboard image, resized and re-encoded for the AI panel.
+#[derive(Debug)]
+struct ImageAttachment {
+    /// Size of the image as pasted.
+    original_size: (u32, u32),
+    image: RgbaImage,
+    /// `image` as PNG, which is what a model would receive.
+    png: Vec<u8>,
+}
+
+impl ImageAttachment {
+    fn summary(&self) -> String {
+        let mut summary = format!("{}x{} PNG, {}", self.image.width, self.image.height, format_bytes(self.png.len() as u64));
+        if self.original_size != (self.image.width, self.image.height) {
+            summary.push_str(&format!(" (scaled from {}x{})", self.original_size.0, self.original_size.1));
+        }
+        summary
+    }
+}
+
+/// Runs a clipboard tool and returns what it printed.
+async fn clipboard_tool_output(program: &str, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => format!("{} not found; install it to paste images", program),
+            _ => format!("Failed to run {}: {}", program, e),
+        })?;
+    if !output.status.success() {
+        return Err("The clipboard holds no image".to_string());
+    }
+    Ok(output.stdout)
+}
+
+/// Reads the clipboard image as PNG through `wl-paste` on Wayland or `xclip` on X11.
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn read_clipboard_png() -> Result<Vec<u8>, String> {
+    let png = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        clipboard_tool_output("wl-paste", &["--no-newline", "--type", "image/png"]).await?
+    } else {
+        clipboard_tool_output("xclip", &["-selection", "clipboard", "-target", "image/png", "-out"]).await?
+    };
+    if png.is_empty() {
+        return Err("The clipboard holds no image".to_string());
+    }
+    Ok(png)
+}
+
+/// Reads the clipboard image as PNG through AppleScript.
+#[cfg(target_os = "macos")]
+async fn read_clipboard_png() -> Result<Vec<u8>, String> {
+    let output = clipboard_tool_output("osascript", &["-e", "the clipboard as «class PNGf»"]).await?;
+    // AppleScript prints the data as «data PNGf89504E47...»
+    let output = String::from_utf8_lossy(&output);
+    let hex = output
+        .trim()
+        .strip_prefix("«data PNGf")
+        .and_then(|hex| hex.strip_suffix('»'))
+        .ok_or("The clipboard holds no image")?;
+    (0..hex.len() / 2)
+        .map(|i| hex.get(i * 2..i * 2 + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| "osascript printed malformed image data".to_string())
+}
+
+/// Reads the clipboard image as PNG through PowerShell, which saves it to a temporary file.
+#[cfg(windows)]
+async fn read_clipboard_png() -> Result<Vec<u8>, String> {
+    let path = std::env::temp_dir().join(format!("neo-term-clipboard-{}.png", std::process::id()));
+    let script = format!(
+        "Add-Type -AssemblyName System.Windows.Forms, System.Drawing; \
+         $image = [Windows.Forms.Clipboard]::GetImage(); \
+         if ($image) {{ $image.Save('{}', [Drawing.Imaging.ImageFormat]::Png) }} else {{ exit 1 }}",
+        path.display().to_string().replace('\'', "''")
+    );
+    // The clipboard API needs a single-threaded apartment
+    clipboard_tool_output("powershell", &["-NoProfile", "-STA", "-Command", &script]).await?;
+    let png = tokio::fs::read(&path).await.map_err(|e| format!("Failed to read the saved clipboard image: {}", e));
+    let _ = tokio::fs::remove_file(&path).await;
+    png
+}
+
+/// Attaches the clipboard image to the AI panel, resized to fit `MAX_IMAGE_SIDE` and `MAX_IMAGE_BYTES`.
+async fn paste_image(tx: mpsc::Sender<AppMessage>) -> Result<(), String> {
+    let png = read_clipboard_png().await?;
+    let attachment = tokio::task::spawn_blocking(move || prepare_attachment(&png, MAX_IMAGE_SIDE, MAX_IMAGE_BYTES))
+        .await
+        .map_err(|e| format!("Image task failed: {}", e))??;
+    tx.send(AppMessage::ImageAttached(attachment))
+        .await
+        .map_err(|_| "Failed to send image attachment".to_string())
+}
+
+/// Decodes a PNG and scales it until its longest side is at most `max_side` and its
+/// re-encoded size at most `max_bytes`.
+fn prepare_attachment(png: &[u8], max_side: u32, max_bytes: usize) -> Result<ImageAttachment, String> {
+    let original = decode_png(png)?;
+    let (mut width, mut height) = fit_within(original.width, original.height, max_side);
+    loop {
+        let image = if (width, height) == (original.width, original.height) {
+            original.clone()
+        } else {
+            downscale(&original, width, height)
+        };
+        let png = encode_png(&image);
+        if png.len() <= max_bytes {
+            return Ok(ImageAttachment {
+                original_size: (original.width, original.height),
+                image,
+                png,
+            });
+        }
+
+        // The encoded size grows roughly with the pixel count
+        let scale = (max_bytes as f64 / png.len() as f64).sqrt() * 0.9;
+        let smaller = fit_within(width, height, ((width.max(height) as f64 * scale) as u32).max(1));
+        if smaller == (width, height) {
+            return Err(format!("Image does not fit in {} even at {}x{}", format_bytes(max_bytes as u64), width, height));
+        }
+        (width, height) = smaller;
+    }
+}
+
+/// Scales `width` x `height` to fit within `max_side`, keeping the aspect ratio.
+fn fit_within(width: u32, height: u32, max_side: u32) -> (u32, u32) {
+    let longest = width.max(height);
+    if longest <= max_side {
+        return (width, height);
+    }
+    let scale = max_side as f64 / longest as f64;
+    let scaled = |side: u32| ((side as f64 * scale).round() as u32).clamp(1, max_side);
+    (scaled(width), scaled(height))
+}
+
+/// Shrinks `image` by averaging the source pixels that cover each target pixel.
+fn downscale(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let (source_width, source_height) = (image.width as usize, image.height as usize);
+    let span = |target: usize, target_len: usize, source_len: usize| {
+        let start = target * source_len / target_len;
+        let end = ((target + 1) * source_len / target_len).max(start + 1);
+        start..end
+    };
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height as usize {
+        let rows = span(y, height as usize, source_height);
+        for x in 0..width as usize {
+            let columns = span(x, width as usize, source_width);
+            let mut sum = [0u64; 4];
+            for row in rows.clone() {
+                let start = (row * source_width + columns.start) * 4;
+                let end = (row * source_width + columns.end) * 4;
+                for pixel in image.pixels[start..end].chunks_exact(4) {
+                    for (total, channel) in sum.iter_mut().zip(pixel) {
+                        *total += u64::from(*channel);
+                    }
+                }
+            }
+            let count = (rows.len() * columns.len()) as u64;
+            pixels.extend(sum.map(|total| ((total + count / 2) / count) as u8));
+        }
+    }
+    RgbaImage { width, height, pixels }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = ((p - i16::from(a)).abs(), (p - i16::from(b)).abs(), (p - i16::from(c)).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Decodes an 8-bit, non-interlaced PNG of any colour type to RGBA. Chunk CRCs and
+/// the zlib checksum are not verified.
+fn decode_png(data: &[u8]) -> Result<RgbaImage, String> {
+    let mut rest = data.strip_prefix(&PNG_SIGNATURE).ok_or("Not a PNG image")?;
+    let (mut header, mut palette, mut transparency, mut compressed) = (None, &[][..], &[][..], Vec::new());
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let body = rest.get(8..8 + length).ok_or("Truncated PNG chunk")?;
+        match &rest[4..8] {
+            b"IHDR" => header = Some(body),
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = rest.get(12 + length..).unwrap_or_default();
+    }
+
+    let header = header.filter(|header| header.len() == 13).ok_or("PNG image has no header")?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let (depth, colour_type, interlace) = (header[8], header[9], header[12]);
+    if depth != 8 {
+        return Err(format!("{}-bit PNG images are not supported", depth));
+    }
+    if interlace != 0 {
+        return Err("Interlaced PNG images are not supported".to_string());
+    }
+    if width == 0 || height == 0 || u64::from(width) * u64::from(height) > MAX_DECODED_PIXELS {
+        return Err(format!("Unsupported PNG size {}x{}", width, height));
+    }
+    let channels = match colour_type {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        other => return Err(format!("Unknown PNG colour type {}", other)),
+    };
+
+    let filtered = zlib_decompress(&compressed)?;
+    let stride = width as usize * channels;
+    if filtered.len() < (stride + 1) * height as usize {
+        return Err("PNG image data is truncated".to_string());
+    }
+    let mut samples = vec![0u8; stride * height as usize];
+    for (y, line) in filtered.chunks_exact(stride + 1).take(height as usize).enumerate() {
+        let (done, current) = samples.split_at_mut(y * stride);
+        let previous = if y > 0 { Some(&done[(y - 1) * stride..]) } else { None };
+        let current = &mut current[..stride];
+        for x in 0..stride {
+            let a = if x >= channels { current[x - channels] } else { 0 };
+            let b = previous.map_or(0, |row| row[x]);
+            let c = if x >= channels { previous.map_or(0, |row| row[x - channels]) } else { 0 };
+            let predictor = match line[0] {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                4 => paeth(a, b, c),
+                other => return Err(format!("Unknown PNG filter type {}", other)),
+            };
+            current[x] = line[x + 1].wrapping_add(predictor);
+        }
+    }
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for sample in samples.chunks_exact(channels) {
+        match (colour_type, sample) {
+            (0, [gray]) => pixels.extend([*gray, *gray, *gray, 255]),
+            (4, [gray, alpha]) => pixels.extend([*gray, *gray, *gray, *alpha]),
+            (2, [r, g, b]) => pixels.extend([*r, *g, *b, 255]),
+            (6, rgba) => pixels.extend_from_slice(rgba),
+            (_, [index]) => {
+                let index = usize::from(*index);
+                let rgb = palette.get(index * 3..index * 3 + 3).ok_or("PNG palette index out of range")?;
+                pixels.extend_from_slice(rgb);
+                pixels.push(transparency.get(index).copied().unwrap_or(255));
+            }
+            _ => unreachable!("samples are split by channel count"),
+        }
+    }
+    Ok(RgbaImage { width, height, pixels })
+}
+
+/// Encodes `image` as PNG, dropping the alpha channel when every pixel is opaque.
+/// Each row uses whichever filter leaves the smallest residuals.
+fn encode_png(image: &RgbaImage) -> Vec<u8> {
+    let opaque = image.pixels.chunks_exact(4).all(|pixel| pixel[3] == 255);
+    let channels = if opaque { 3 } else { 4 };
+    let samples: Vec<u8> = if opaque {
+        image.pixels.chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect()
+    } else {
+        image.pixels.clone()
+    };
+
+    let stride = image.width as usize * channels;
+    let zeros = vec![0u8; stride];
+    let mut filtered = Vec::with_capacity((stride + 1) * image.height as usize);
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    for (y, row) in samples.chunks_exact(stride).enumerate() {
+        let previous = if y > 0 { &samples[(y - 1) * stride..y * stride] } else { &zeros[..] };
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+        for filter in 0..5u8 {
+            for x in 0..stride {
+                let a = if x >= channels { row[x - channels] } else { 0 };
+                let c = if x >= channels { previous[x - channels] } else { 0 };
+                let predictor = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => previous[x],
+                    3 => ((u16::from(a) + u16::from(previous[x])) / 2) as u8,
+                    _ => paeth(a, previous[x], c),
+                };
+                candidate[x] = row[x].wrapping_sub(predictor);
+            }
+            let cost = candidate.iter().map(|&value| u64::from((value as i8).unsigned_abs())).sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                best.copy_from_slice(&candidate);
+            }
+        }
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend(image.width.to_be_bytes());
+    header.extend(image.height.to_be_bytes());
+    header.extend([8, if opaque { 2 } else { 6 }, 0, 0, 0]);
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    for (kind, body) in [(b"IHDR", header), (b"IDAT", zlib_compress(&filtered)), (b"IEND", Vec::new())] {
+        png.extend((body.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(&body);
+        let crc = crc32(&png[start..]);
+        png.extend(crc.to_be_bytes());
+    }
+    png
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut value = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 != 0 { 0xedb88320 ^ (value >> 1) } else { value >> 1 };
+            bit += 1;
+        }
+        table[n] = value;
+        n += 1;
+    }
+    table
+};
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| CRC32_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8))
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b overflows
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Base lengths of DEFLATE length codes 257..=285, and their extra bits.
+const DEFLATE_LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const DEFLATE_LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+/// Base distances of DEFLATE distance codes 0..=29, and their extra bits.
+const DEFLATE_DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DEFLATE_DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Code lengths of the fixed literal/length and distance codes (RFC 1951 section 3.2.6).
+fn fixed_code_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut literals = [8u8; 288];
+    literals[144..256].fill(9);
+    literals[256..280].fill(7);
+    (literals, [5; 30])
+}
+
+/// Reads a DEFLATE stream, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u8) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.data.get(self.position / 8).ok_or("Truncated deflate stream")?;
+            value |= u32::from((byte >> (self.position % 8)) & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// A canonical Huffman code, decoded one bit at a time.
+struct Huffman {
+    /// How many codes there are of each length.
+    counts: [u16; 16],
+    /// Symbols ordered by code.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[usize::from(offsets[usize::from(length)])] = symbol as u16;
+                offsets[usize::from(length)] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        // Codes of each length follow the last code of the previous length, so the
+        // symbol index is found by comparing against the first code of each length
+        let (mut code, mut first, mut index) = (0usize, 0usize, 0usize);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as usize;
+            let count = usize::from(count);
+            if code < first + count {
+                return self.symbols.get(index + code - first).copied().ok_or_else(|| "Invalid Huffman code".to_string());
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code".to_string())
+    }
+}
+
+/// Decompresses a zlib stream (RFC 1950).
+fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    match data {
+        [method, flags, ..] if method & 0x0f == 8 && ((u16::from(*method) << 8) | u16::from(*flags)) % 31 == 0 && flags & 0x20 == 0 => {
+            inflate(&data[2..])
+        }
+        _ => Err("Invalid zlib header".to_string()),
+    }
+}
+
+/// Decompresses a raw DEFLATE stream (RFC 1951).
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader { data, position: 0 };
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                let start = reader.position.div_ceil(8);
+                let header = data.get(start..start + 4).ok_or("Truncated deflate stream")?;
+                let length = usize::from(u16::from_le_bytes([header[0], header[1]]));
+                if u16::from_le_bytes([header[0], header[1]]) != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err("Corrupt stored deflate block".to_string());
+                }
+                output.extend_from_slice(data.get(start + 4..start + 4 + length).ok_or("Truncated deflate stream")?);
+                reader.position = (start + 4 + length) * 8;
+            }
+            1 => {
+                let (literals, distances) = fixed_code_lengths();
+                inflate_block(&mut reader, &mut output, &Huffman::new(&literals), &Huffman::new(&distances))?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err("Invalid deflate block type".to_string()),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+/// Reads the code length tables at the start of a dynamic Huffman block.
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let length_count = reader.bits(4)? as usize + 4;
+
+    let mut length_lengths = [0u8; 19];
+    for &index in &ORDER[..length_count] {
+        length_lengths[index] = reader.bits(3)? as u8;
+    }
+    let length_code = Huffman::new(&length_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match length_code.decode(reader)? {
+            length @ 0..=15 => (length as u8, 1),
+            16 => (*lengths.last().ok_or("Deflate length repeat with nothing to repeat")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("Deflate code lengths overrun".to_string());
+    }
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals), Huffman::new(distances)))
+}
+
+/// Decodes one Huffman-coded block into `output`.
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), String> {
+    loop {
+        match literals.decode(reader)? {
+            literal @ 0..=255 => output.push(literal as u8),
+            256 => return Ok(()),
+            symbol => {
+                let index = usize::from(symbol - 257);
+                let base = DEFLATE_LENGTH_BASE.get(index).ok_or("Invalid deflate length code")?;
+                let length = usize::from(*base) + reader.bits(DEFLATE_LENGTH_EXTRA[index])? as usize;
+                let index = usize::from(distances.decode(reader)?);
+                let base = DEFLATE_DISTANCE_BASE.get(index).ok_or("Invalid deflate distance code")?;
+                let distance = usize::from(*base) + reader.bits(DEFLATE_DISTANCE_EXTRA[index])? as usize;
+                if distance > output.len() {
+                    return Err("Deflate distance reaches before the start of the stream".to_string());
+                }
+                // Copied a byte at a time since the match may overlap what it produces
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+}
+
+/// Writes a DEFLATE stream, least significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, count: u32) {
+        self.buffer |= u64::from(value) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from their most significant bit.
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    /// Writes `symbol` with the fixed literal/length code.
+    fn fixed_literal(&mut self, symbol: u16) {
+        let symbol = u32::from(symbol);
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
+
+/// Compresses `data` as a zlib stream: one fixed-Huffman block of LZ77 matches found
+/// through hash chains. Filtered screenshots compress well enough without dynamic codes.
+fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    const WINDOW: usize = 32 * 1024;
+    const HASH_BITS: u32 = 15;
+    const MAX_CHAIN: usize = 32;
+    const MIN_MATCH: usize = 3;
+    const MAX_MATCH: usize = 258;
+
+    let hash = |at: usize| {
+        let key = (u32::from(data[at]) << 16) | (u32::from(data[at + 1]) << 8) | u32::from(data[at + 2]);
+        (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    };
+    // Positions are stored plus one, so zero means none
+    let mut head = vec![0usize; 1 << HASH_BITS];
+    let mut previous = vec![0usize; WINDOW];
+    let insert = |at: usize, head: &mut [usize], previous: &mut [usize]| {
+        if at + MIN_MATCH <= data.len() {
+            let bucket = hash(at);
+            previous[at % WINDOW] = head[bucket];
+            head[bucket] = at + 1;
+        }
+    };
+
+    let mut writer = BitWriter::default();
+    writer.bits(1, 1); // last block
+    writer.bits(1, 2); // fixed Huffman codes
+    let mut at = 0;
+    while at < data.len() {
+        let mut best = (0, 0);
+        if at + MIN_MATCH <= data.len() {
+            let limit = (data.len() - at).min(MAX_MATCH);
+            let mut candidate = head[hash(at)];
+            for _ in 0..MAX_CHAIN {
+                if candidate == 0 || at - (candidate - 1) > WINDOW - 1 {
+                    break;
+                }
+                let start = candidate - 1;
+                let length = data[start..].iter().zip(&data[at..at + limit]).take_while(|(a, b)| a == b).count();
+                if length > best.0 {
+                    best = (length, at - start);
+                    if length == limit {
+                        break;
+                    }
+                }
+                candidate = previous[start % WINDOW];
+                // Chains can hold entries overwritten by newer positions
+                if candidate > start {
+                    break;
+                }
+            }
+        }
+
+        let (length, distance) = best;
+        if length >= MIN_MATCH {
+            let index = DEFLATE_LENGTH_BASE.iter().rposition(|&base| usize::from(base) <= length).unwrap_or(0);
+            writer.fixed_literal(257 + index as u16);
+            writer.bits((length - usize::from(DEFLATE_LENGTH_BASE[index])) as u32, u32::from(DEFLATE_LENGTH_EXTRA[index]));
+            let index = DEFLATE_DISTANCE_BASE.iter().rposition(|&base| usize::from(base) <= distance).unwrap_or(0);
+            writer.code(index as u32, 5);
+            writer.bits((distance - usize::from(DEFLATE_DISTANCE_BASE[index])) as u32, u32::from(DEFLATE_DISTANCE_EXTRA[index]));
+            for position in at..at + length {
+                insert(position, &mut head, &mut previous);
+            }
+            at += length;
+        } else {
+            writer.fixed_literal(u16::from(data[at]));
+            insert(at, &mut head, &mut previous);
+            at += 1;
+        }
+    }
+    writer.fixed_literal(256);
+
+    let mut output = vec![0x78, 0x01];
+    output.extend(writer.finish());
+    output.extend(adler32(data).to_be_bytes());
+    output
+}
+
+/// Formats a stress report together with the frame times the UI recorded during the run.
+fn stress_summary(report: &StressReport, frame_times: &mut [Duration]) -> Vec<String> {
+    let seconds = report.elapsed.as_secs_f64().max(f64::EPSILON);
+    let mut lines = vec![
+        format!(
+            "[STRESS] {} of {} lines sent in {:.2}s ({:.0} lines/s)",
+            report.sent,
+            report.requested,
+            seconds,
+            report.sent as f64 / seconds
+        ),
+        format!(
+            "[STRESS] Dropped: {} ({:.1}%)",
+            report.dropped,
+            report.dropped as f64 * 100.0 / report.requested.max(1) as f64
+        ),
+    ];
+
+    if frame_times.is_empty() {
+        lines.push("[STRESS] No frames rendered during the run.".to_string());
+    } else {
+        frame_times.sort();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let average = frame_times.iter().sum::<Duration>() / frame_times.len() as u32;
+        let p95 = frame_times[(frame_times.len() - 1) * 95 / 100];
+        lines.push(format!(
+            "[STRESS] {} frames: avg {:.1}ms, p95 {:.1}ms, max {:.1}ms",
+            frame_times.len(),
+            ms(average),
+            ms(p95),
+            ms(frame_times[frame_times.len() - 1])
+        ));
+    }
+    lines
+}
+
+/// Identifies a background task tracked in `AppState::running_tasks`.
+type TaskId = u64;
+
+/// Latest progress published by a running task.
+#[derive(Debug, Clone)]
+struct TaskProgress {
+    /// Completed fraction in `0.0..=1.0`, or `None` when the total is unknown.
+    fraction: Option<f32>,
+    detail: String,
+}
+
+/// Handed to a task by `AppState::spawn_task_with_progress` to publish its progress.
+struct ProgressReporter {
+    tx: mpsc::Sender<AppMessage>,
+    id: TaskId,
+}
+
+impl ProgressReporter {
+    /// Progress is best-effort: a full channel drops the update rather than slowing the task.
+    fn report(&self, fraction: Option<f32>, detail: String) {
+        let _ = self.tx.try_send(AppMessage::TaskProgress(self.id, TaskProgress { fraction, detail }));
+    }
+}
+
+/// The kinds of dialog a script can ask the UI to show.
+#[derive(Debug)]
+enum DialogKind {
+    Message,
+    Confirm,
+    Input { default: String },
+    Select { options: Vec<String> },
+}
+
+/// The user's answer to a dialog, delivered back to the awaiting script.
+#[derive(Debug)]
+enum DialogResponse {
+    Dismissed,
+    Confirmed(bool),
+    Input(Option<String>),
+    Selected(Option<String>),
+}
+
+/// A dialog request along with the channel its answer is sent on.
+#[derive(Debug)]
+struct DialogRequest {
+    title: String,
+    text: String,
+    kind: DialogKind,
+    respond_to: oneshot::Sender<DialogResponse>,
+}
+
+/// Async handle that scripts and plugins use to ask the user questions.
+///
+/// Every call resolves once the user answers. If the window closes first the
+/// request is dropped and the call resolves as if the dialog was cancelled.
+#[derive(Clone)]
+struct DialogApi {
+    sender: mpsc::Sender<AppMessage>,
+    waker: UiWaker,
+}
+
+impl DialogApi {
+    async fn show(&self, title: &str, text: &str, kind: DialogKind) -> Option<DialogResponse> {
+        let (respond_to, response) = oneshot::channel();
+        let request = DialogRequest {
+            title: title.to_string(),
+            text: text.to_string(),
+            kind,
+            respond_to,
+        };
+        self.sender.send(AppMessage::ShowDialog(request)).await.ok()?;
+        // The UI may be idle in ControlFlow::Wait, so wake it to pick the dialog up
+        self.waker.wake();
+        response.await.ok()
+    }
+
+    async fn message(&self, title: &str, text: &str) {
+        self.show(title, text, DialogKind::Message).await;
+    }
+
+    async fn confirm(&self, title: &str, text: &str) -> bool {
+        matches!(
+            self.show(title, text, DialogKind::Confirm).await,
+            Some(DialogResponse::Confirmed(true))
+        )
+    }
+
+    async fn input(&self, title: &str, prompt: &str, default: &str) -> Option<String> {
+        let kind = DialogKind::Input { default: default.to_string() };
+        match self.show(title, prompt, kind).await {
+            Some(DialogResponse::Input(value)) => value,
+            _ => None,
+        }
+    }
+
+    async fn select(&self, title: &str, prompt: &str, options: Vec<String>) -> Option<String> {
+        match self.show(title, prompt, DialogKind::Select { options }).await {
+            Some(DialogResponse::Selected(choice)) => choice,
+            _ => None,
+        }
+    }
+}
+
+/// A dialog currently queued for display, with the widget state it keeps between frames.
+struct ActiveDialog {
+    request: DialogRequest,
+    input: String,
+    selected: usize,
+}
+
+impl ActiveDialog {
+    fn new(request: DialogRequest) -> Self {
+        let input = match &request.kind {
+            DialogKind::Input { default } => default.clone(),
+            _ => String::new(),
+        };
+        Self {
+            request,
+            input,
+            selected: 0,
+        }
+    }
+}
+
+/// How loudly a highlighted output line should be shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// A case-insensitive substring that marks matching output lines.
+struct HighlightRule {
+    pattern: &'static str,
+    severity: Severity,
+}
+
+const HIGHLIGHT_RULES: &[HighlightRule] = &[
+    HighlightRule { pattern: "error", severity: Severity::Error },
+    HighlightRule { pattern: "failed", severity: Severity::Error },
+    HighlightRule { pattern: "panic", severity: Severity::Error },
+    HighlightRule { pattern: "fatal", severity: Severity::Error },
+    HighlightRule { pattern: "[fail]", severity: Severity::Error },
+    HighlightRule { pattern: "warn", severity: Severity::Warning },
+];
+
+/// Returns the severity of the first highlight rule matching `line`, if any.
+fn classify_line(line: &str) -> Option<Severity> {
+    let line = line.to_lowercase();
+    HIGHLIGHT_RULES
+        .iter()
+        .find(|rule| line.contains(rule.pattern))
+        .map(|rule| rule.severity)
+}
+
+fn severity_color(severity: Severity) -> Color32 {
+    match severity {
+        Severity::Error => Color32::from_rgb(255, 64, 64),
+        Severity::Warning => Color32::from_rgb(255, 200, 0),
+    }
+}
+
+/// How the window reacts to background output matching an error rule while unfocused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AttentionMode {
+    /// Ignore errors entirely.
+    Off,
+    /// Count unseen errors in the title and status bar and ask the OS for attention.
+    Badge,
+    /// Like `Badge`, but also raise and focus the window.
+    Focus,
+}
+
+/// What happened at a point on the activity timeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimelineKind {
+    Command,
+    Error,
+    TaskCompleted,
+}
+
+/// A marker on the activity timeline, pointing at the buffer line it was recorded on.
+struct TimelineEvent {
+    kind: TimelineKind,
+    line_number: usize,
+    time: chrono::DateTime<chrono::Local>,
+    label: String,
+}
+
+/// Manages the terminal's text content with scrolling support.
+struct TextBuffer {
+    lines: Vec<String>,
+    /// Highlight of each entry in `lines`; only background output is classified.
+    severities: Vec<Option<Severity>>,
+    max_lines: usize,
+    scroll_position: usize,
+    /// Absolute line number of `lines[0]`, so markers stay valid as old lines are dropped.
+    first_line_number: usize,
+    timeline: Vec<TimelineEvent>,
+    created: chrono::DateTime<chrono::Local>,
+}
+
+impl TextBuffer {
+    fn new(max_lines: usize) -> Self {
+        Self {
+            lines: Vec::with_capacity(max_lines),
+            severities: Vec::with_capacity(max_lines),
+            max_lines,
+            scroll_position: 0,
+            first_line_number: 0,
+            timeline: Vec::new(),
+            created: chrono::Local::now(),
+        }
+    }
+
+    fn add_line(&mut self, line: String) {
+        self.add_highlighted_line(line, None);
+    }
+
+    fn add_highlighted_line(&mut self, line: String, severity: Option<Severity>) {
+        if self.lines.len() >= self.max_lines {
+            self.lines.remove(0);
+            self.severities.remove(0);
+            self.first_line_number += 1;
+            let first = self.first_line_number;
+            self.timeline.retain(|event| event.line_number >= first);
+            if self.scroll_position > 0 {
+                self.scroll_position -= 1;
+            }
+        }
+        self.lines.push(line);
+        self.severities.push(severity);
+        // Auto-scroll to bottom when new line is added
+        self.scroll_position = self.lines.len().saturating_sub(self.max_lines);
+    }
+
+    fn scroll_up(&mut self) {
+        if self.scroll_position > 0 {
+            self.scroll_position -= 1;
+        }
+    }
+
+    fn scroll_down(&mut self) {
+        let max_scroll = self.lines.len().saturating_sub(self.max_lines);
+        if self.scroll_position < max_scroll {
+            self.scroll_position += 1;
+        }
+    }
+
+    fn scroll_to_top(&mut self) {
+        self.scroll_position = 0;
+    }
+
+    fn scroll_to_bottom(&mut self) {
+        self.scroll_position = self.lines.len().saturating_sub(self.max_lines);
+    }
+
+    fn visible_lines(&self) -> &[String] {
+        let start = self.scroll_position;
+        let end = (start + self.max_lines).min(self.lines.len());
+        if start < self.lines.len() {
+            &self.lines[start..end]
+        } else {
+            &[]
+        }
+    }
+
+    fn is_at_bottom(&self) -> bool {
+        self.scroll_position >= self.lines.len().saturating_sub(self.max_lines)
+    }
+
+    /// Absolute line number the next added line will get.
+    fn next_line_number(&self) -> usize {
+        self.first_line_number + self.lines.len()
+    }
+
+    /// An empty buffer that carries on this one's line numbering, so line numbers
+    /// held elsewhere never point at lines added after a clear.
+    fn cleared(&self) -> Self {
+        let mut buffer = Self::new(self.max_lines);
+        buffer.first_line_number = self.next_line_number();
+        buffer
+    }
+
+    /// Absolute line number of the `index`-th entry returned by `visible_lines`.
+    fn visible_line_number(&self, index: usize) -> usize {
+        self.first_line_number + self.scroll_position + index
+    }
+
+    /// Returns the line with the given absolute line number, if it is still in the buffer.
+    fn line(&self, line_number: usize) -> Option<&String> {
+        self.lines.get(line_number.checked_sub(self.first_line_number)?)
+    }
+
+    /// Highlight the line with the given absolute line number was added with.
+    fn severity(&self, line_number: usize) -> Option<Severity> {
+        *self.severities.get(line_number.checked_sub(self.first_line_number)?)?
+    }
+
+    /// Records a timeline marker pointing at the most recently added line.
+    fn mark(&mut self, kind: TimelineKind, label: String) {
+        let line_number = (self.first_line_number + self.lines.len()).saturating_sub(1);
+        self.timeline.push(TimelineEvent {
+            kind,
+            line_number,
+            time: chrono::Local::now(),
+            label,
+        });
+    }
+}
+
+/// One side of the two-pane compare view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum CompareSide {
+    Left,
+    Right,
+}
+
+/// Two-pane compare mode. Each pane holds a selection of absolute buffer line
+/// numbers as `(anchor, end)`; the differing lines are recomputed when either changes.
+#[derive(Default)]
+struct CompareState {
+    left: Option<(usize, usize)>,
+    right: Option<(usize, usize)>,
+    diff: Option<(Vec<bool>, Vec<bool>)>,
+}
+
+impl CompareState {
+    fn selection(&self, side: CompareSide) -> Option<(usize, usize)> {
+        let (anchor, end) = match side {
+            CompareSide::Left => self.left?,
+            CompareSide::Right => self.right?,
+        };
+        Some((anchor.min(end), anchor.max(end)))
+    }
+
+    /// Starts a selection at `line_number`, or extends the existing one when `extend` is set.
+    fn select(&mut self, side: CompareSide, line_number: usize, extend: bool) {
+        let selection = match side {
+            CompareSide::Left => &mut self.left,
+            CompareSide::Right => &mut self.right,
+        };
+        *selection = match *selection {
+            Some((anchor, _)) if extend => Some((anchor, line_number)),
+            _ => Some((line_number, line_number)),
+        };
+        self.diff = None;
+    }
+
+    /// Clamps both selections to the lines still in the buffer, dropping a selection
+    /// that was evicted entirely, so the diff stays aligned with the drawn lines.
+    fn drop_evicted(&mut self, first_line_number: usize) {
+        for selection in [&mut self.left, &mut self.right] {
+            let Some((anchor, end)) = *selection else {
+                continue;
+            };
+            let clamped = if anchor.max(end) < first_line_number {
+                None
+            } else {
+                Some((anchor.max(first_line_number), end.max(first_line_number)))
+            };
+            if clamped != *selection {
+                *selection = clamped;
+                self.diff = None;
+            }
+        }
+    }
+
+    /// Diffs the two selections, caching the result until a selection changes.
+    fn update_diff(&mut self, buffer: &TextBuffer) {
+        self.drop_evicted(buffer.first_line_number);
+        if self.diff.is_some() {
+            return;
+        }
+        let (Some(left), Some(right)) = (self.selection(CompareSide::Left), self.selection(CompareSide::Right)) else {
+            return;
+        };
+        let collect = |(start, end): (usize, usize)| -> Vec<&String> {
+            (start..=end).filter_map(|number| buffer.line(number)).collect()
+        };
+        self.diff = Some(diff_lines(&collect(left), &collect(right)));
+    }
+}
+
+/// Line diff via longest common subsequence. Returns, for each line of `a` and of `b`,
+/// whether it differs (is not part of the common subsequence).
+fn diff_lines(a: &[&String], b: &[&String]) -> (Vec<bool>, Vec<bool>) {
+    let (n, m) = (a.len(), b.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut a_differs = vec![true; n];
+    let mut b_differs = vec![true; m];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            a_differs[i] = false;
+            b_differs[j] = false;
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    (a_differs, b_differs)
+}
+
+/// Holds the entire application state.
+struct AppState {
+    text_buffer: TextBuffer,
+    status_message: String,
+    message_receiver: mpsc::Receiver<AppMessage>,
+    command_input: String,
+    dialogs: VecDeque<ActiveDialog>,
+    waker: UiWaker,
+    attention_mode: AttentionMode,
+    window_focused: bool,
+    unseen_errors: usize,
+    last_error: Option<String>,
+    /// Absolute buffer line the console should scroll to on the next frame.
+    scroll_to_line: Option<usize>,
+    compare: Option<CompareState>,
+    running_tasks: BTreeMap<TaskId, String>,
+    task_progress: BTreeMap<TaskId, TaskProgress>,
+    next_task_id: TaskId,
+    confirm_close: bool,
+    close_prompt_open: bool,
+    window_request: Option<WindowRequest>,
+    stats: SessionStats,
+    /// The running stress task, if any, and the frame times recorded during it.
+    stress_task: Option<TaskId>,
+    stress_frame_times: Option<Vec<Duration>>,
+    demo_task: Option<TaskId>,
+    primary_selection: Option<PrimarySelection>,
+    launcher: Option<LauncherState>,
+    throughput: ThroughputMeter,
+    touch: TouchState,
+    remote: RemoteState,
+    pinned_commands: Vec<String>,
+    /// Events from `AppMessage::Input`, fed to egui with the next frame's input.
+    pending_input: Vec<egui::Event>,
+    ai_panel: AiPanelState,
+}
+
+/// The AI side panel: a prompt and the images attached to it. No model is connected
+/// yet, so both stay on this machine.
+#[derive(Default)]
+struct AiPanelState {
+    open: bool,
+    prompt: String,
+    attachments: Vec<AiAttachment>,
+}
+
+struct AiAttachment {
+    attachment: ImageAttachment,
+    /// Thumbnail, uploaded the first time the panel draws it.
+    texture: Option<egui::TextureHandle>,
+}
+
+/// Counters collected over the lifetime of the window, reported on quit.
+struct SessionStats {
+    started: chrono::DateTime<chrono::Local>,
+    commands_run: usize,
+    lines_received: usize,
+    tasks_completed: usize,
+    tasks_failed: usize,
+}
+
+impl SessionStats {
+    fn new() -> Self {
+        Self {
+            started: chrono::Local::now(),
+            commands_run: 0,
+            lines_received: 0,
+            tasks_completed: 0,
+            tasks_failed: 0,
+        }
+    }
+
+    fn summary(&self) -> Vec<String> {
+        let duration = chrono::Local::now() - self.started;
+        let peak_memory = match peak_memory_kb() {
+            Some(kb) => format!("{:.1} MB", kb as f64 / 1024.0),
+            None => "n/a".to_string(),
+        };
+        vec![
+            "Session statistics:".to_string(),
+            format!(
+                "  Duration:        {:02}:{:02}:{:02}",
+                duration.num_hours(),
+                duration.num_minutes() % 60,
+                duration.num_seconds() % 60
+            ),
+            format!("  Commands run:    {}", self.commands_run),
+            format!("  Lines received:  {}", self.lines_received),
+            format!("  Tasks completed: {}", self.tasks_completed),
+            format!("  Tasks failed:    {}", self.tasks_failed),
+            format!("  Peak memory:     {}", peak_memory),
+        ]
+    }
+
+    /// Appends a one-line record of this session to `path`, for long-term tracking.
+    fn append_to_history(&self, path: &str) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(
+            file,
+            "{} duration_s={} commands={} lines={} tasks_completed={} tasks_failed={} peak_memory_kb={}",
+            self.started.to_rfc3339(),
+            (chrono::Local::now() - self.started).num_seconds(),
+            self.commands_run,
+            self.lines_received,
+            self.tasks_completed,
+            self.tasks_failed,
+            peak_memory_kb().map_or("n/a".to_string(), |kb| kb.to_string())
+        )
+    }
+}
+
+/// Peak resident memory of this process in KiB, where the platform exposes it.
+fn peak_memory_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Number of one-second buckets shown by the status bar sparklines.
+const THROUGHPUT_HISTORY: usize = 60;
+
+/// Rolling per-second message counts and worst frame times for the status bar.
+struct ThroughputMeter {
+    /// Messages drained per completed second, oldest first.
+    messages: VecDeque<u32>,
+    /// Slowest frame per completed second, in milliseconds.
+    frame_ms: VecDeque<f32>,
+    bucket_started: Instant,
+    bucket_messages: u32,
+    bucket_frame_ms: f32,
+}
+
+impl ThroughputMeter {
+    fn new() -> Self {
+        Self {
+            messages: VecDeque::from(vec![0; THROUGHPUT_HISTORY]),
+            frame_ms: VecDeque::from(vec![0.0; THROUGHPUT_HISTORY]),
+            bucket_started: Instant::now(),
+            bucket_messages: 0,
+            bucket_frame_ms: 0.0,
+        }
+    }
+
+    /// Closes every bucket that has fully elapsed, filling idle seconds with zeros.
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.bucket_started).as_secs();
+        if elapsed == 0 {
+            return;
+        }
+        for second in 0..elapsed.min(THROUGHPUT_HISTORY as u64) {
+            let (messages, frame_ms) = if second == 0 {
+                (self.bucket_messages, self.bucket_frame_ms)
+            } else {
+                (0, 0.0)
+            };
+            self.messages.pop_front();
+            self.messages.push_back(messages);
+            self.frame_ms.pop_front();
+            self.frame_ms.push_back(frame_ms);
+        }
+        self.bucket_started += Duration::from_secs(elapsed);
+        self.bucket_messages = 0;
+        self.bucket_frame_ms = 0.0;
+    }
+
+    fn record_frame(&mut self, messages: u32, frame_time: Duration) {
+        self.roll(Instant::now());
+        self.bucket_messages += messages;
+        self.bucket_frame_ms = self.bucket_frame_ms.max(frame_time.as_secs_f32() * 1000.0);
+    }
+
+    /// Whether a redraw is needed to move the graphs along while the window is idle.
+    fn needs_tick(&self) -> bool {
+        self.bucket_started.elapsed() >= Duration::from_secs(1)
+            && (self.bucket_messages > 0 || self.messages.iter().any(|&count| count > 0))
+    }
+}
+
+/// Draws a small line graph of `values` scaled to their maximum.
+fn sparkline(ui: &mut egui::Ui, values: impl ExactSizeIterator<Item = f32>, color: Color32) -> egui::Response {
+    let (rect, response) = ui.allocate_exact_size(Vec2::new(THROUGHPUT_HISTORY as f32, 14.0), egui::Sense::hover());
+    let values: Vec<f32> = values.collect();
+    let max = values.iter().copied().fold(0.0, f32::max);
+    if max > 0.0 {
+        let step = rect.width() / (values.len().max(2) - 1) as f32;
+        let points = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| egui::pos2(rect.left() + i as f32 * step, rect.bottom() - value / max * rect.height()))
+            .collect();
+        ui.painter().add(egui::Shape::line(points, Stroke::new(1.0, color)));
+    } else {
+        ui.painter().hline(rect.x_range(), rect.bottom(), Stroke::new(1.0, color.gamma_multiply(0.4)));
+    }
+    response
+}
+
+/// Environment variable naming a file that session statistics are appended to on quit.
+const SESSION_HISTORY_ENV: &str = "NEO_TERM_SESSION_HISTORY";
+
+/// A window operation requested from the UI, applied by the event loop after the frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WindowRequest {
+    Quit,
+    Minimize,
+    ToggleFullscreen,
+}
+
+/// Menu bar menus, laid out like a native macOS application.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MenuGroup {
+    App,
+    Edit,
+    View,
+    Window,
+}
+
+/// An entry in the command registry. Help text, the menu bar and keyboard shortcuts
+/// are all generated from `COMMANDS`.
+struct CommandSpec {
+    /// How the command is typed, with placeholders for arguments.
+    usage: &'static str,
+    description: &'static str,
+    /// Menu and item label. Only for commands without arguments, which run as `usage`.
+    menu: Option<(MenuGroup, &'static str)>,
+    shortcut: Option<KeyboardShortcut>,
+}
+
+/// State for `--launcher` mode: a command palette that runs one command, shows its
+/// output and then dismisses the window.
+struct LauncherState {
+    selected: usize,
+    /// Absolute buffer line where the command's output starts, once a command has run.
+    output_start: Option<usize>,
+}
+
+/// Registry entries whose usage contains the first word of `query`, for the launcher palette.
+/// An exact command name comes first, then names starting with the word.
+fn palette_matches(query: &str) -> Vec<&'static CommandSpec> {
+    let word = query.split_whitespace().next().unwrap_or_default().to_lowercase();
+    let mut matches: Vec<_> = COMMANDS.iter().filter(|spec| spec.usage.contains(word.as_str())).collect();
+    matches.sort_by_key(|spec| {
+        let name = spec.usage.split(' ').next().unwrap_or_default();
+        (name != word, !name.starts_with(word.as_str()))
+    });
+    matches
+}
+
+/// Toggles fullscreen: Ctrl+Cmd+F on macOS, F11 elsewhere.
+const FULLSCREEN_SHORTCUT: KeyboardShortcut = if cfg!(target_os = "macos") {
+    KeyboardShortcut::new(Modifiers::MAC_CMD.plus(Modifiers::CTRL), Key::F)
+} else {
+    KeyboardShortcut::new(Modifiers::NONE, Key::F11)
+};
+
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec { usage: "help", description: "Show this help message (alias: ?)", menu: Some((MenuGroup::App, "Help")), shortcut: None },
+    CommandSpec { usage: "clear", description: "Clear the terminal", menu: Some((MenuGroup::Edit, "Clear Buffer")), shortcut: Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::K)) },
+    CommandSpec { usage: "status", description: "Show system status", menu: Some((MenuGroup::App, "Status")), shortcut: None },
+    CommandSpec { usage: "echo <text>", description: "Echo text back", menu: None, shortcut: None },
+    CommandSpec { usage: "time", description: "Show current time", menu: None, shortcut: None },
+    CommandSpec { usage: "date", description: "Show current date", menu: None, shortcut: None },
+    CommandSpec { usage: "async-task", description: "Run async task", menu: None, shortcut: None },
+    CommandSpec { usage: "log", description: "Generate log entry", menu: None, shortcut: None },
+    CommandSpec { usage: "scroll-top", description: "Scroll to top", menu: Some((MenuGroup::View, "Scroll to Top")), shortcut: None },
+    CommandSpec { usage: "scroll-bottom", description: "Scroll to bottom", menu: Some((MenuGroup::View, "Scroll to Bottom")), shortcut: None },
+    CommandSpec { usage: "dialog <kind> <text>", description: "Ask via a message|confirm|input|select dialog", menu: None, shortcut: None },
+    CommandSpec { usage: "focus-follows <mode>", description: "React to background errors: off|badge|focus", menu: None, shortcut: None },
+    CommandSpec { usage: "ai", description: "Toggle the AI side panel", menu: Some((MenuGroup::View, "AI Panel")), shortcut: None },
+    CommandSpec { usage: "paste-image", description: "Attach the clipboard image to the AI panel", menu: Some((MenuGroup::Edit, "Paste Image")), shortcut: Some(KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::I)) },
+    CommandSpec { usage: "compare", description: "Toggle two-pane compare mode for selections", menu: Some((MenuGroup::View, "Compare Mode")), shortcut: None },
+    CommandSpec { usage: "tasks", description: "List running background tasks", menu: Some((MenuGroup::View, "Running Tasks")), shortcut: None },
+    CommandSpec { usage: "session-stats", description: "Show statistics for this session", menu: Some((MenuGroup::App, "Session Statistics")), shortcut: None },
+    CommandSpec { usage: "stress lines <n> [rate]", description: "Flood output with n lines (rate per second)", menu: None, shortcut: None },
+    CommandSpec { usage: "demo play <script> [times]", description: "Replay a scripted demo", menu: None, shortcut: None },
+    CommandSpec { usage: "profile <name>", description: "Run a profile's health checks, then its startup command", menu: None, shortcut: None },
+    CommandSpec { usage: "fetch <url> [-o path] [--sha256 <hash>]", description: "Download a file over HTTP, verifying its checksum", menu: None, shortcut: None },
+    CommandSpec { usage: "run <command>", description: "Run a shell command (NEO_TERM_SHELL=sh|cmd|powershell)", menu: None, shortcut: None },
+    CommandSpec { usage: "touch", description: "Toggle touch mode (or touch on|off)", menu: Some((MenuGroup::View, "Touch Mode")), shortcut: None },
+    CommandSpec { usage: "remote", description: "Toggle remote/gamepad navigation (or remote on|off)", menu: Some((MenuGroup::View, "Remote Navigation")), shortcut: None },
+    CommandSpec { usage: "pin <command>", description: "Pin a command for remote navigation", menu: None, shortcut: None },
+    CommandSpec { usage: "unpin <n>", description: "Remove the n-th pinned command", menu: None, shortcut: None },
+    CommandSpec { usage: "pins", description: "List pinned commands", menu: None, shortcut: None },
+    CommandSpec { usage: "confirm-close <on|off>", description: "Ask before closing with tasks running", menu: None, shortcut: None },
+    CommandSpec { usage: "new-window", description: "Open another Neo-Term window", menu: Some((MenuGroup::Window, "New Window")), shortcut: Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::T)) },
+    CommandSpec { usage: "fullscreen", description: "Toggle fullscreen", menu: Some((MenuGroup::Window, "Toggle Fullscreen")), shortcut: Some(FULLSCREEN_SHORTCUT) },
+    CommandSpec { usage: "minimize", description: "Minimize the window", menu: Some((MenuGroup::Window, "Minimize")), shortcut: Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::M)) },
+    CommandSpec { usage: "close", description: "Close the window", menu: Some((MenuGroup::Window, "Close Window")), shortcut: Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::W)) },
+    CommandSpec { usage: "quit", description: "Quit Neo-Term", menu: Some((MenuGroup::App, "Quit Neo-Term")), shortcut: Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::Q)) },
+];
+
+impl AppState {
+    fn new(
+        message_receiver: mpsc::Receiver<AppMessage>,
+        waker: UiWaker,
+        primary_selection: Option<PrimarySelection>,
+        launcher: bool,
+    ) -> Self {
+        Self {
+            text_buffer: TextBuffer::new(1000),
+            status_message: "STATUS: System nominal.".to_string(),
+            message_receiver,
+            command_input: String::new(),
+            dialogs: VecDeque::new(),
+            waker,
+            attention_mode: AttentionMode::Badge,
+            window_focused: true,
+            unseen_errors: 0,
+            last_error: None,
+            scroll_to_line: None,
+            compare: None,
+            running_tasks: BTreeMap::new(),
+            task_progress: BTreeMap::new(),
+            next_task_id: 0,
+            confirm_close: true,
+            close_prompt_open: false,
+            window_request: None,
+            stats: SessionStats::new(),
+            stress_task: None,
+            stress_frame_times: None,
+            demo_task: None,
+            throughput: ThroughputMeter::new(),
+            touch: TouchState::default(),
+            remote: RemoteState::default(),
+            pinned_commands: Vec::new(),
+            pending_input: Vec::new(),
+            ai_panel: AiPanelState::default(),
+            primary_selection,
+            launcher: launcher.then_some(LauncherState {
+                selected: 0,
+                output_start: None,
+            }),
+        }
+    }
+
+    /// Adds a line of background output, tracking it for attention if it is an
+    /// unseen error. Returns `true` when the line should raise the window's attention.
+    fn add_output_line(&mut self, line: String) -> bool {
+        self.stats.lines_received += 1;
+        let severity = classify_line(&line);
+        let is_error = severity == Some(Severity::Error);
+        let raise = is_error && self.attention_mode != AttentionMode::Off && !self.window_focused;
+        if raise {
+            self.unseen_errors += 1;
+            self.last_error = Some(line.clone());
+        }
+        if is_error {
+            self.text_buffer.add_highlighted_line(line.clone(), severity);
+            self.text_buffer.mark(TimelineKind::Error, line);
+        } else {
+            self.text_buffer.add_highlighted_line(line, severity);
+        }
+        raise
+    }
+
+    /// Spawns a background task that is listed as running until it finishes.
+    /// A task fails if it returns an error or panics.
+    fn spawn_task<F>(&mut self, sender: &mpsc::Sender<AppMessage>, name: &str, task: F) -> TaskId
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.spawn_task_with_progress(sender, name, |_| task)
+    }
+
+    /// Like `spawn_task`, but builds the task from a `ProgressReporter` whose
+    /// updates are shown as a progress bar until the task finishes.
+    fn spawn_task_with_progress<F, T>(&mut self, sender: &mpsc::Sender<AppMessage>, name: &str, make_task: T) -> TaskId
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+        T: FnOnce(ProgressReporter) -> F,
+    {
+        let id = self.next_task_id;
+        self.next_task_id += 1;
+        self.running_tasks.insert(id, name.to_string());
+        let task = make_task(ProgressReporter { tx: sender.clone(), id });
+
+        let tx = sender.clone();
+        tokio::spawn(async move {
+            // Run the work as its own task so a panic still reports it as finished
+            let outcome = match tokio::spawn(task).await {
+                Ok(result) => result,
+                Err(e) => Err(format!("task panicked: {}", e)),
+            };
+            if tx.send(AppMessage::TaskFinished(id, outcome)).await.is_err() {
+                eprintln!("Failed to send task finished message");
+            }
+        });
+        id
+    }
+
+    /// Applies every queued background message. Returns how many there were and
+    /// whether one should raise the window's attention.
+    fn apply_messages(&mut self, sender: &mpsc::Sender<AppMessage>) -> (u32, bool) {
+        let mut raise_attention = false;
+        let mut messages_drained = 0;
+        while let Ok(message) = self.message_receiver.try_recv() {
+            messages_drained += 1;
+            match message {
+                AppMessage::TaskCompleted(result) => {
+                    self.status_message = format!("STATUS: {}", result);
+                    raise_attention |= self.add_output_line(format!("[ASYNC] {}", result));
+                    self.text_buffer.mark(TimelineKind::TaskCompleted, result);
+                }
+                AppMessage::NewLine(line) => raise_attention |= self.add_output_line(line),
+                AppMessage::ShowDialog(request) => {
+                    self.dialogs.push_back(ActiveDialog::new(request));
+                }
+                AppMessage::TaskFinished(id, outcome) => {
+                    let name = self.running_tasks.remove(&id).unwrap_or_default();
+                    self.task_progress.remove(&id);
+                    // A failed stress run never sends its report, so end the run here too
+                    if self.stress_task == Some(id) {
+                        self.stress_task = None;
+                        self.stress_frame_times = None;
+                    }
+                    if self.demo_task == Some(id) {
+                        self.demo_task = None;
+                    }
+                    match outcome {
+                        Ok(()) => self.stats.tasks_completed += 1,
+                        Err(e) => {
+                            self.stats.tasks_failed += 1;
+                            raise_attention |= self.add_output_line(format!("[TASK] {} failed: {}", name, e));
+                        }
+                    }
+                }
+                AppMessage::TaskProgress(id, progress) => {
+                    // Late updates from a task that already finished are ignored
+                    if self.running_tasks.contains_key(&id) {
+                        self.task_progress.insert(id, progress);
+                    }
+                }
+                AppMessage::SetInput(text) => self.command_input = text,
+                AppMessage::RunCommand(command) => submit_command(&command, self, sender.clone()),
+                // Gamepad readers keep running while remote mode is off
+                AppMessage::Remote(action) if self.remote.enabled => apply_remote_action(self, sender, action),
+                AppMessage::Remote(_) => {}
+                AppMessage::Input(event) => self.pending_input.push(event),
+                AppMessage::ImageAttached(attachment) => {
+                    self.text_buffer.add_line(format!("[AI] Attached {}", attachment.summary()));
+                    self.ai_panel.open = true;
+                    self.ai_panel.attachments.push(AiAttachment { attachment, texture: None });
+                }
+                AppMessage::StressFinished(report) => {
+                    let mut frame_times = self.stress_frame_times.take().unwrap_or_default();
+                    for line in stress_summary(&report, &mut frame_times) {
+                        self.text_buffer.add_line(line);
+                    }
+                }
+            }
+            // Commands queued behind a quit, e.g. by a demo, must not run
+            if self.window_request == Some(WindowRequest::Quit) {
+                break;
+            }
+        }
+        (messages_drained, raise_attention)
+    }
+
+    /// Runs one frame without touching the window: applies queued messages, lays out
+    /// the UI and hands it to `renderer`. Headless runs and tests drive frames with it too.
+    fn run_frame(
+        &mut self,
+        egui_ctx: &Context,
+        renderer: &mut dyn Renderer,
+        sender: &mpsc::Sender<AppMessage>,
+        mut raw_input: egui::RawInput,
+    ) -> FrameOutput {
+        let frame_started = Instant::now();
+        let (messages_drained, raise_attention) = self.apply_messages(sender);
+
+        raw_input.events.append(&mut self.touch.pending_keys);
+        raw_input.events.append(&mut self.pending_input);
+        let output = egui_ctx.run(raw_input, |ctx| {
+            draw_ui(ctx, self, sender.clone());
+        });
+
+        // egui folds the native (possibly fractional) scale factor and its own zoom into this
+        let pixels_per_point = output.pixels_per_point;
+        let paint_jobs = egui_ctx.tessellate(output.shapes, pixels_per_point);
+        renderer.render(&paint_jobs, &output.textures_delta, pixels_per_point);
+
+        let frame_time = frame_started.elapsed();
+        self.throughput.record_frame(messages_drained, frame_time);
+        if let Some(frame_times) = self.stress_frame_times.as_mut() {
+            frame_times.push(frame_time);
+        }
+
+        FrameOutput {
+            raise_attention,
+            repaint_now: output.viewport_output.get(&ViewportId::ROOT).is_some_and(|viewport| viewport.repaint_delay.is_zero()),
+            platform_output: output.platform_output,
+        }
+    }
+
+    /// Quits, unless tasks are running and the user wants to be asked first.
+    fn request_close(&mut self) {
+        if self.confirm_close && !self.running_tasks.is_empty() {
+            self.close_prompt_open = true;
+        } else {
+            self.window_request = Some(WindowRequest::Quit);
+        }
+    }
+
+    fn clear_attention(&mut self) {
+        self.unseen_errors = 0;
+        self.last_error = None;
+    }
+
+    fn window_title(&self) -> String {
+        if self.unseen_errors > 0 {
+            format!("Neo-Term [{} ERR]", self.unseen_errors)
+        } else {
+            "Neo-Term".to_string()
+        }
+    }
+}
+
+/// Touch-screen support: larger hit targets and the on-screen key row.
+#[derive(Default)]
+struct TouchState {
+    enabled: bool,
+    /// Set once `touch on|off` was used, so a touch no longer switches the mode on.
+    chosen: bool,
+    /// Whether the current theme was built with touch spacing.
+    styled: bool,
+    /// Ctrl was tapped on the key row and applies to the next key.
+    ctrl_latched: bool,
+    /// Key presses from the key row, fed to egui with the next frame's input.
+    pending_keys: Vec<egui::Event>,
+}
+
+/// Keys on the on-screen key row, in display order after Esc, Tab and Ctrl.
+const TOUCH_ARROW_KEYS: &[(&str, Key)] = &[
+    ("←", Key::ArrowLeft),
+    ("↑", Key::ArrowUp),
+    ("↓", Key::ArrowDown),
+    ("→", Key::ArrowRight),
+];
+
+/// Lines moved by PageUp/PageDown in remote navigation mode.
+const REMOTE_PAGE_LINES: isize = 10;
+
+/// Remote navigation: scrolling and pinned commands driven by the few keys a
+/// presenter remote sends, or by a gamepad.
+#[derive(Default)]
+struct RemoteState {
+    enabled: bool,
+    /// Index into `AppState::pinned_commands` of the highlighted command.
+    selected: usize,
+    /// Console scroll offset and line height as of the last frame.
+    offset: f32,
+    row_height: f32,
+    /// Offset to scroll the console to on the next frame.
+    scroll_to: Option<f32>,
+    gamepads_opened: bool,
+}
+
+/// What a remote key or gamepad button does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RemoteAction {
+    /// Scroll the console by this many lines, negative towards the top.
+    Scroll(isize),
+    ScrollTop,
+    ScrollBottom,
+    PreviousPin,
+    NextPin,
+    RunPin,
+}
+
+/// Size of a Linux joystick event (`struct js_event` in linux/joystick.h).
+#[cfg(any(target_os = "linux", test))]
+const JS_EVENT_SIZE: usize = 8;
+#[cfg(any(target_os = "linux", test))]
+const JS_EVENT_BUTTON: u8 = 0x01;
+#[cfg(any(target_os = "linux", test))]
+const JS_EVENT_AXIS: u8 = 0x02;
+/// Set on the synthetic events reporting each control's state when the device is opened.
+#[cfg(any(target_os = "linux", test))]
+const JS_EVENT_INIT: u8 = 0x80;
+
+/// Turns joystick events from an Xbox-style pad into remote actions: D-pad or left
+/// stick to scroll and pick pins, A to run, LB/RB to page and Back/Start to jump.
+#[cfg(any(target_os = "linux", test))]
+#[derive(Default)]
+struct GamepadMapper {
+    /// Direction each axis was last pushed in, so a held stick fires once.
+    held: [i8; 8],
+}
+
+#[cfg(any(target_os = "linux", test))]
+impl GamepadMapper {
+    const PUSHED: i16 = 16_384;
+    const CENTRED: u16 = 8_192;
+
+    fn map(&mut self, event: [u8; JS_EVENT_SIZE]) -> Option<RemoteAction> {
+        let value = i16::from_ne_bytes([event[4], event[5]]);
+        let (kind, number) = (event[6], event[7]);
+        if kind & JS_EVENT_INIT != 0 {
+            return None;
+        }
+        match kind {
+            JS_EVENT_BUTTON if value == 1 => match number {
+                0 => Some(RemoteAction::RunPin),
+                4 => Some(RemoteAction::Scroll(-REMOTE_PAGE_LINES)),
+                5 => Some(RemoteAction::Scroll(REMOTE_PAGE_LINES)),
+                6 => Some(RemoteAction::ScrollTop),
+                7 => Some(RemoteAction::ScrollBottom),
+                _ => None,
+            },
+            // Left stick on axes 0/1 and the D-pad on 6/7; the triggers rest fully negative, so skip them
+            JS_EVENT_AXIS if matches!(number, 0 | 1 | 6 | 7) => {
+                let direction = if value >= Self::PUSHED {
+                    1
+                } else if value <= -Self::PUSHED {
+                    -1
+                } else if value.unsigned_abs() < Self::CENTRED {
+                    0
+                } else {
+                    return None;
+                };
+                let held = &mut self.held[number as usize];
+                if direction == std::mem::replace(held, direction) {
+                    return None;
+                }
+                let horizontal = number % 2 == 0;
+                match (horizontal, direction) {
+                    (true, -1) => Some(RemoteAction::PreviousPin),
+                    (true, 1) => Some(RemoteAction::NextPin),
+                    (false, -1) => Some(RemoteAction::Scroll(-1)),
+                    (false, 1) => Some(RemoteAction::Scroll(1)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads every `/dev/input/js*` joystick on its own thread, sending mapped actions as
+/// `AppMessage::Remote`. Returns how many were opened.
+#[cfg(target_os = "linux")]
+fn open_gamepads(sender: &mpsc::Sender<AppMessage>, waker: &UiWaker) -> usize {
+    use std::io::Read;
+
+    let Ok(entries) = std::fs::read_dir("/dev/input") else {
+        return 0;
+    };
+    let mut opened = 0;
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with("js") {
+            continue;
+        }
+        let Ok(mut device) = std::fs::File::open(entry.path()) else {
+            continue;
+        };
+        let (sender, waker) = (sender.clone(), waker.clone());
+        std::thread::spawn(move || {
+            let mut mapper = GamepadMapper::default();
+            let mut event = [0; JS_EVENT_SIZE];
+            // Ends when the pad is unplugged or the UI has gone
+            while device.read_exact(&mut event).is_ok() {
+                if let Some(action) = mapper.map(event) {
+                    if sender.blocking_send(AppMessage::Remote(action)).is_err() {
+                        break;
+                    }
+                    waker.wake();
+                }
+            }
+        });
+        opened += 1;
+    }
+    opened
+}
+
+/// Gamepads are only read through the Linux joystick API so far.
+#[cfg(not(target_os = "linux"))]
+fn open_gamepads(_sender: &mpsc::Sender<AppMessage>, _waker: &UiWaker) -> usize {
+    0
+}
+
+/// Id of the command input, so the key row can give it focus back.
+const COMMAND_INPUT_ID: &str = "command_input";
+
+/// Enlarges widgets and spacing to finger size.
+fn apply_touch_spacing(style: &mut Style) {
+    style.spacing.interact_size = Vec2::new(56.0, 44.0);
+    style.spacing.button_padding = Vec2::new(14.0, 10.0);
+    style.spacing.item_spacing = Vec2::new(12.0, 12.0);
+    style.spacing.scroll.bar_width = 16.0;
+}
+
+/// Creates the "Hacker Theme" as specified in THEMING_SYSTEM.md.
+fn create_hacker_theme() -> Style {
+    let hacker_green = Color32::from_rgb(0, 255, 68);
+    let background_dark = Color32::from_rgb(10, 10, 10);
+    let mid_gray = Color32::from_rgb(60, 60, 60);
+    let light_gray = Color32::from_rgb(100, 100, 100);
+
+    let mut style = Style::default();
+
+    style.visuals = Visuals {
+        dark_mode: true,
+        override_text_color: Some(hacker_green),
+        panel_fill: background_dark,
+        window_rounding: Rounding::ZERO,
+        window_stroke: Stroke::new(1.0, mid_gray),
+        selection: egui::style::Selection {
+            bg_fill: Color32::from_rgba_premultiplied(
+                hacker_green.r(),
+                hacker_green.g(),
+                hacker_green.b(),
+                50,
+            ),
+            stroke: Stroke::new(1.0, hacker_green),
+        },
+        ..Visuals::dark()
+    };
+
+    style.spacing = Spacing {
+        item_spacing: Vec2::new(8.0, 8.0),
+        ..Spacing::default()
+    };
+
+    style.text_styles = [
+        (
+            TextStyle::Heading,
+            FontId::new(24.0, FontFamily::Monospace),
+        ),
+        (TextStyle::Body, FontId::new(16.0, FontFamily::Monospace)),
+        (TextStyle::Button, FontId::new(16.0, FontFamily::Monospace)),
+        (
+            TextStyle::Monospace,
+            FontId::new(16.0, FontFamily::Monospace),
+        ),
+        (TextStyle::Small, FontId::new(12.0, FontFamily::Monospace)),
+    ]
+    .into();
+
+    let widget_visuals = &mut style.visuals.widgets;
+    widget_visuals.inactive = egui::style::WidgetVisuals {
+        bg_fill: mid_gray,
+        fg_stroke: Stroke::new(1.0, hacker_green),
+        rounding: Rounding::ZERO,
+        bg_stroke: Stroke::new(1.0, hacker_green),
+        ..widget_visuals.inactive
+    };
+    widget_visuals.hovered = egui::style::WidgetVisuals {
+        bg_fill: light_gray,
+        fg_stroke: Stroke::new(2.0, hacker_green),
+        bg_stroke: Stroke::new(1.0, hacker_green),
+        ..widget_visuals.hovered
+    };
+    widget_visuals.active = egui::style::WidgetVisuals {
+        bg_fill: background_dark,
+        fg_stroke: Stroke::new(2.0, hacker_green),
+        bg_stroke: Stroke::new(2.0, hacker_green),
+        ..widget_visuals.active
+    };
+
+    style
+}
+
+/// Options given on the command line.
+#[derive(Debug, Default)]
+struct LaunchOptions {
+    /// `--launcher`: open as a one-shot command palette.
+    launcher: bool,
+    /// `--profile <name>`: open the profile once the window is up.
+    profile: Option<String>,
+}
+
+impl LaunchOptions {
+    fn from_args() -> Result<Self, String> {
+        let mut options = LaunchOptions::default();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--launcher" => options.launcher = true,
+                "--profile" => options.profile = Some(args.next().ok_or("--profile needs a profile name")?),
+                other => return Err(format!("Unknown argument '{}'. Usage: neo-gui [--launcher] [--profile <name>]", other)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// How often an idle window checks the message channel for background output.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Environment variable controlling window decorations: `auto` (default) or `none`.
+///
+/// With `auto` the compositor is asked for server-side decorations and winit falls back
+/// to drawing its own when they are refused, as on GNOME. `none` suits tiling
+/// compositors like Sway or Hyprland that are commonly run without title bars.
+const DECORATIONS_ENV: &str = "NEO_TERM_DECORATIONS";
+
+/// The Wayland application id, used by compositors for window rules.
+#[cfg(target_os = "linux")]
+const APP_ID: &str = "neo-term";
+
+/// Primary selection support on Wayland: selected text is offered for middle-click paste.
+#[cfg(target_os = "linux")]
+struct PrimarySelection {
+    clipboard: smithay_clipboard::Clipboard,
+    last_stored: String,
+}
+
+#[cfg(target_os = "linux")]
+impl PrimarySelection {
+    /// Connects to the window's Wayland display, or returns `None` on other display servers.
+    fn new(window: &Window) -> Option<Self> {
+        use winit::raw_window_handle::{HasDisplayHandle, RawDisplayHandle};
+
+        let RawDisplayHandle::Wayland(handle) = window.display_handle().ok()?.as_raw() else {
+            return None;
+        };
+        // SAFETY: the display belongs to the event loop, which outlives the app state
+        // holding this clipboard. egui-winit sets up its own clipboard the same way.
+        let clipboard = unsafe { smithay_clipboard::Clipboard::new(handle.display.as_ptr()) };
+        Some(Self {
+            clipboard,
+            last_stored: String::new(),
+        })
+    }
+
+    fn store(&mut self, text: &str) {
+        if text != self.last_stored {
+            self.clipboard.store_primary(text);
+            self.last_stored = text.to_string();
+        }
+    }
+
+    fn load(&self) -> Option<String> {
+        self.clipboard.load_primary().ok()
+    }
+}
+
+/// Other platforms have no primary selection.
+#[cfg(not(target_os = "linux"))]
+struct PrimarySelection;
+
+#[cfg(not(target_os = "linux"))]
+impl PrimarySelection {
+    fn new(_window: &Window) -> Option<Self> {
+        None
+    }
+
+    fn store(&mut self, _text: &str) {}
+
+    fn load(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Environment variable selecting the rendering backend (`wgpu` or `null`).
+const RENDERER_ENV: &str = "NEO_TERM_RENDERER";
+
+/// Draws egui output to the window. Each graphics backend implements this so
+/// `NeoTermApp::run` does not depend on any of them.
+trait Renderer {
+    /// Called when the window's drawable size changes.
+    fn resize(&mut self, width: u32, height: u32);
+
+    /// Applies texture changes and draws one frame.
+    fn render(
+        &mut self,
+        paint_jobs: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+        pixels_per_point: f32,
+    );
+}
+
+/// The rendering backends that can be selected at runtime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RendererKind {
+    Wgpu,
+    Null,
+}
+
+impl RendererKind {
+    fn from_env() -> Result<Self, String> {
+        match std::env::var(RENDERER_ENV).unwrap_or_default().to_lowercase().as_str() {
+            "" | "wgpu" => Ok(RendererKind::Wgpu),
+            "null" => Ok(RendererKind::Null),
+            other => Err(format!("Unknown renderer '{}'. Use wgpu or null.", other)),
+        }
+    }
+}
+
+/// Hardware-accelerated renderer using egui-wgpu.
+struct WgpuRenderer {
+    surface: wgpu::Surface<'static>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    egui_renderer: EguiRenderer,
+}
+
+impl WgpuRenderer {
+    async fn new(window: Arc<Window>) -> Result<Self, Box<dyn std::error::Error>> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let surface = instance.create_surface(window.clone())?;
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                compatible_surface: Some(&surface),
+                ..Default::default()
+            })
+            .await
+            .ok_or("Failed to find suitable adapter")?;
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .await?;
+
+        let format = surface
+            .get_capabilities(&adapter)
+            .formats
+            .into_iter()
+            .find(|format| format.is_srgb())
+            .unwrap_or(wgpu::TextureFormat::Bgra8UnormSrgb);
+        let size = window.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        surface.configure(&device, &config);
+
+        let egui_renderer = EguiRenderer::new(&device, format, None, 1);
+
+        Ok(Self {
+            surface,
+            device,
+            queue,
+            config,
+            egui_renderer,
+        })
+    }
+
+    fn draw_frame(&mut self, frame: wgpu::SurfaceTexture, paint_jobs: &[egui::ClippedPrimitive], screen_descriptor: &ScreenDescriptor) {
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        self.egui_renderer.update_buffers(&self.device, &self.queue, &mut encoder, paint_jobs, screen_descriptor);
+
+        // Matches the hacker theme's panel_fill
+        let clear_color = wgpu::Color { r: 10.0/255.0, g: 10.0/255.0, b: 10.0/255.0, a: 1.0 };
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(clear_color), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.egui_renderer.render(&mut render_pass, paint_jobs, screen_descriptor);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+    }
+}
+
+impl Renderer for WgpuRenderer {
+    fn resize(&mut self, width: u32, height: u32) {
+        self.config.width = width.max(1);
+        self.config.height = height.max(1);
+        self.surface.configure(&self.device, &self.config);
+    }
+
+    fn render(
+        &mut self,
+        paint_jobs: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+        pixels_per_point: f32,
+    ) {
+        let screen_descriptor = ScreenDescriptor {
+            size_in_pixels: [self.config.width, self.config.height],
+            pixels_per_point,
+        };
+
+        // egui sends each texture delta once (the font atlas only on the first frame),
+        // so apply them even when this frame cannot be drawn
+        for (id, image_delta) in &textures_delta.set {
+            self.egui_renderer.update_texture(&self.device, &self.queue, *id, image_delta);
+        }
+
+        match self.surface.get_current_texture() {
+            Ok(frame) => self.draw_frame(frame, paint_jobs, &screen_descriptor),
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                // Reconfigure and pick the frame up on the next redraw
+                self.surface.configure(&self.device, &self.config);
+            }
+            Err(e) => eprintln!("Failed to get surface texture: {:?}", e),
+        }
+
+        for id in &textures_delta.free {
+            self.egui_renderer.free_texture(id);
+        }
+    }
+}
+
+/// Renderer that draws nothing. Selecting it runs Neo-Term headless, with no window
+/// or display server (see `run_headless`).
+struct NullRenderer;
+
+impl Renderer for NullRenderer {
+    fn resize(&mut self, _width: u32, _height: u32) {}
+
+    fn render(&mut self, _paint_jobs: &[egui::ClippedPrimitive], _textures_delta: &egui::TexturesDelta, _pixels_per_point: f32) {}
+}
+
+/// What a frame asks of the window once it has been drawn.
+struct FrameOutput {
+    raise_attention: bool,
+    platform_output: egui::PlatformOutput,
+    /// Kinetic scrolling and animations want the next frame straight away.
+    repaint_now: bool,
+}
+
+/// Wakes the UI thread from background work. Headless runs have no event loop and
+/// poll on a timer instead, so there waking does nothing.
+#[derive(Clone)]
+struct UiWaker(Option<EventLoopProxy<()>>);
+
+impl UiWaker {
+    fn wake(&self) {
+        if let Some(proxy) = &self.0 {
+            let _ = proxy.send_event(());
+        }
+    }
+}
+
+/// Main application struct that manages all resources
+struct NeoTermApp {
+    event_loop: Option<EventLoop<()>>,
+    window: Option<Arc<Window>>,
+    renderer: Option<Box<dyn Renderer>>,
+    egui_ctx: Context,
+    egui_state: Option<EguiWinitState>,
+    app_state: AppState,
+    _message_sender: mpsc::Sender<AppMessage>, // Keep sender alive
+}
+
+impl NeoTermApp {
+    /// Creates the app. With `options.launcher` set, the window opens as a
+    /// borderless, centered command palette instead of the full console.
+    async fn new(options: LaunchOptions) -> Result<Self, Box<dyn std::error::Error>> {
+        let launcher = options.launcher;
+        env_logger::init();
+
+        let event_loop = EventLoop::new()?;
+        let decorations = match std::env::var(DECORATIONS_ENV).unwrap_or_default().to_lowercase().as_str() {
+            "" | "auto" => true,
+            "none" => false,
+            other => return Err(format!("Unknown decorations mode '{}'. Use auto or none.", other).into()),
+        };
+        let window_builder = WindowBuilder::new()
+            .with_title("Neo-Term")
+            .with_decorations(decorations)
+            // Keeps client-side decorations dark to match the hacker theme
+            .with_theme(Some(Theme::Dark));
+        #[cfg(target_os = "linux")]
+        let window_builder = winit::platform::wayland::WindowBuilderExtWayland::with_name(window_builder, APP_ID, APP_ID);
+        let window_builder = if launcher {
+            window_builder
+                .with_decorations(false)
+                .with_resizable(false)
+                .with_inner_size(LogicalSize::new(720.0, 400.0))
+                .with_window_level(WindowLevel::AlwaysOnTop)
+        } else {
+            window_builder
+        };
+        let window = Arc::new(window_builder.build(&event_loop)?);
+        if launcher {
+            // Wayland compositors place windows themselves and ignore this
+            if let Some(monitor) = window.current_monitor() {
+                let (screen, size) = (monitor.size(), window.outer_size());
+                let x = monitor.position().x + (screen.width.saturating_sub(size.width) / 2) as i32;
+                let y = monitor.position().y + (screen.height.saturating_sub(size.height) / 3) as i32;
+                window.set_outer_position(PhysicalPosition::new(x, y));
+            }
+        }
+
+        let renderer: Box<dyn Renderer> = Box::new(WgpuRenderer::new(window.clone()).await?);
+
+        let egui_ctx = Context::default();
+        let egui_state = EguiWinitState::new(egui_ctx.clone(), ViewportId::ROOT, &event_loop, None, None);
+        egui_ctx.set_style(create_hacker_theme());
+
+        let (message_sender, message_receiver) = mpsc::channel::<AppMessage>(1000);
+
+        let mut app_state = AppState::new(message_receiver, UiWaker(Some(event_loop.create_proxy())), PrimarySelection::new(&window), launcher);
+
+        // Initialize with ASCII art
+        let ascii_art = [
+            "███╗   ██╗███████╗ ██████╗",
+            "████╗  ██║██╔════╝██╔═══██╗",
+            "██╔██╗ ██║█████╗  ██║   ██║",
+            "██║╚██╗██║██╔══╝  ██║   ██║",
+            "██║ ╚████║███████╗╚██████╔╝",
+            "╚═╝  ╚═══╝╚══════╝ ╚═════╝ ",
+            "",
+            "Welcome to Neo-Term. Standby for commands."
+        ];
+        for line in &ascii_art {
+            app_state.text_buffer.add_line(line.to_string());
+        }
+
+        if let Some(profile) = &options.profile {
+            submit_command(&format!("profile {}", profile), &mut app_state, message_sender.clone());
+        }
+
+        Ok(Self {
+            event_loop: Some(event_loop),
+            window: Some(window),
+            renderer: Some(renderer),
+            egui_ctx,
+            egui_state: Some(egui_state),
+            app_state,
+            _message_sender: message_sender,
+        })
+    }
+
+    fn run(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let event_loop = self.event_loop.take().unwrap();
+        let window = self.window.take().unwrap();
+        let mut renderer = self.renderer.take().unwrap();
+        let mut egui_state = self.egui_state.take().unwrap();
+
+        event_loop.run(move |event, elwt| {
+            elwt.set_control_flow(ControlFlow::Poll);
+
+            match event {
+                Event::WindowEvent { window_id, event } if window_id == window.id() => {
+                    let response = egui_state.on_window_event(&window, &event);
+                    if matches!(event, WindowEvent::Touch(_)) && !self.app_state.touch.chosen {
+                        self.app_state.touch.enabled = true;
+                    }
+                    if response.repaint {
+                        window.request_redraw();
+                    }
+                    if response.consumed {
+                        return;
+                    }
+
+                    match event {
+                        WindowEvent::CloseRequested => {
+                            self.app_state.request_close();
+                            // Exit right away when nothing needs confirming: minimized or occluded
+                            // windows may not get another redraw to apply the request
+                            if self.app_state.window_request == Some(WindowRequest::Quit) {
+                                self.app_state.window_request = None;
+                                elwt.exit();
+                            } else {
+                                window.request_redraw();
+                            }
+                        }
+                        WindowEvent::Focused(focused) => {
+                            self.app_state.window_focused = focused;
+                            if focused && self.app_state.unseen_errors > 0 {
+                                self.app_state.clear_attention();
+                                window.set_title(&self.app_state.window_title());
+                            }
+                        }
+                        WindowEvent::Resized(new_size) => renderer.resize(new_size.width, new_size.height),
+                        WindowEvent::ScaleFactorChanged { .. } => {
+                            // Fractional scales change the physical size without always
+                            // sending a Resized event, so resync the surface here
+                            let size = window.inner_size();
+                            renderer.resize(size.width, size.height);
+                            window.request_redraw();
+                        }
+                        WindowEvent::RedrawRequested => {
+                            let raw_input = egui_state.take_egui_input(&window);
+                            let sender = mpsc::Sender::clone(&self._message_sender);
+                            let frame = self.app_state.run_frame(&self.egui_ctx, renderer.as_mut(), &sender, raw_input);
+
+                            if frame.raise_attention {
+                                window.set_title(&self.app_state.window_title());
+                                window.request_user_attention(Some(UserAttentionType::Informational));
+                                if self.app_state.attention_mode == AttentionMode::Focus {
+                                    window.focus_window();
+                                }
+                            }
+
+                            egui_state.handle_platform_output(&window, frame.platform_output);
+
+                            // Kinetic scrolling and animations ask for the next frame straight away
+                            if frame.repaint_now {
+                                window.request_redraw();
+                            }
+
+                            match self.app_state.window_request.take() {
+                                Some(WindowRequest::Quit) => elwt.exit(),
+                                Some(WindowRequest::Minimize) => window.set_minimized(true),
+                                Some(WindowRequest::ToggleFullscreen) => {
+                                    // Borderless fullscreen is a native fullscreen Space on macOS
+                                    let fullscreen = match window.fullscreen() {
+                                        Some(_) => None,
+                                        None => Some(Fullscreen::Borderless(None)),
+                                    };
+                                    window.set_fullscreen(fullscreen);
+                                }
+                                None => {}
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                #[cfg(target_os = "macos")]
+                Event::Resumed => native_menu::install(mpsc::Sender::clone(&self._message_sender), self.app_state.waker.clone()),
+                Event::UserEvent(()) => window.request_redraw(),
+                Event::AboutToWait => {
+                    // Check if we have new messages to process
+                    if !self.app_state.message_receiver.is_empty() || self.app_state.throughput.needs_tick() {
+                        window.request_redraw();
+                    } else {
+                        // Throttle redraws to reduce CPU usage, but wake up periodically
+                        // so background output is noticed even without input events
+                        elwt.set_control_flow(ControlFlow::WaitUntil(Instant::now() + IDLE_POLL_INTERVAL));
+                    }
+                }
+                _ => (),
+            }
+        })?;
+
+        // Resources will be automatically cleaned up when the struct is dropped
+        Ok(())
+    }
+}
+
+impl Drop for NeoTermApp {
+    fn drop(&mut self) {
+        report_session(&self.app_state.stats);
+        println!("NeoTermApp: Cleaning up resources...");
+        // All resources are automatically cleaned up when Arc and Box are dropped
+        // Explicit cleanup is handled by the respective libraries
+    }
+}
+
+/// Prints the session summary and appends it to the history file, if one is set.
+fn report_session(stats: &SessionStats) {
+    for line in stats.summary() {
+        println!("{}", line);
+    }
+    if let Ok(path) = std::env::var(SESSION_HISTORY_ENV) {
+        if let Err(e) = stats.append_to_history(&path) {
+            eprintln!("Failed to append session statistics to {}: {}", path, e);
+        }
+    }
+}
+
+/// Size of the virtual screen that headless frames are laid out on.
+const HEADLESS_SCREEN_SIZE: Vec2 = Vec2::new(1280.0, 800.0);
+
+/// Input for a headless frame: the virtual screen and no events.
+fn headless_input() -> egui::RawInput {
+    egui::RawInput {
+        screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, HEADLESS_SCREEN_SIZE)),
+        ..Default::default()
+    }
+}
+
+/// Runs with the null renderer and no window or display server: commands are read
+/// from stdin, one per line, and console output is written to stdout. Ends on `quit`,
+/// or at the end of input once every task has finished.
+async fn run_headless(options: LaunchOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if options.launcher {
+        return Err("The launcher needs a window; use the wgpu renderer.".into());
+    }
+    env_logger::init();
+
+    let egui_ctx = Context::default();
+    egui_ctx.set_style(create_hacker_theme());
+    let (sender, receiver) = mpsc::channel::<AppMessage>(1000);
+    let mut state = AppState::new(receiver, UiWaker(None), None, false);
+    // Nobody can answer the close prompt
+    state.confirm_close = false;
+    if let Some(profile) = &options.profile {
+        submit_command(&format!("profile {}", profile), &mut state, sender.clone());
+    }
+
+    let input = tokio::spawn({
+        let sender = sender.clone();
+        async move {
+            use tokio::io::AsyncBufReadExt;
+            let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if sender.send(AppMessage::RunCommand(line)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut renderer = NullRenderer;
+    let mut printed = 0;
+    loop {
+        state.run_frame(&egui_ctx, &mut renderer, &sender, headless_input());
+        for number in printed..state.text_buffer.next_line_number() {
+            if let Some(line) = state.text_buffer.line(number) {
+                println!("{}", line);
+            }
+        }
+        printed = state.text_buffer.next_line_number();
+
+        let input_done = input.is_finished() && state.message_receiver.is_empty() && state.running_tasks.is_empty();
+        if state.window_request.take() == Some(WindowRequest::Quit) || input_done {
+            break;
+        }
+        tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+    }
+    report_session(&state.stats);
+    Ok(())
+}
+
+/// Echoes and records a command as entered by the user, then runs it.
+fn submit_command(command: &str, state: &mut AppState, sender: mpsc::Sender<AppMessage>) {
+    state.text_buffer.add_line(format!("> {}", command));
+    state.text_buffer.mark(TimelineKind::Command, command.to_string());
+    state.stats.commands_run += 1;
+    process_command(command, state, sender);
+}
+
+/// The arguments after `prefix` with their original case: commands are matched
+/// lowercased, but names, paths, URLs and command lines are case-sensitive.
+fn raw_args<'a>(command: &'a str, prefix: &str) -> &'a str {
+    command.trim().get(prefix.len()..).unwrap_or_default().trim()
+}
+
+fn process_command(command: &str, state: &mut AppState, sender: mpsc::Sender<AppMessage>) {
+    let cmd = command.trim().to_lowercase();
+
+    match cmd.as_str() {
+        "help" | "?" => {
+            state.text_buffer.add_line("Available commands:".to_string());
+            for spec in COMMANDS {
+                state.text_buffer.add_line(format!("  {:<16} - {}", spec.usage, spec.description));
+            }
+        }
+        "clear" => {
+            state.text_buffer = state.text_buffer.cleared();
+            state.scroll_to_line = None;
+            state.text_buffer.add_line("Terminal cleared.".to_string());
+        }
+        "status" => {
+            state.text_buffer.add_line(format!("System Status: {}", state.status_message));
+        }
+        cmd if cmd.starts_with("echo ") => {
+            let echo_text = &cmd[5..];
+            state.text_buffer.add_line(echo_text.to_string());
+        }
+        "time" => {
+            let time = chrono::Local::now().format("%H:%M:%S");
+            state.text_buffer.add_line(format!("Current time: {}", time));
+        }
+        "date" => {
+            let date = chrono::Local::now().format("%Y-%m-%d");
+            state.text_buffer.add_line(format!("Current date: {}", date));
+        }
+        "async-task" => {
+            let tx = sender.clone();
+            state.spawn_task(&sender, "async-task", async move {
+                if tx.send(AppMessage::NewLine("[COMMAND] Async task started...".to_string())).await.is_err() {
+                    return Err("Failed to send command response".to_string());
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                tx.send(AppMessage::TaskCompleted("Command executed successfully.".to_string()))
+                    .await
+                    .map_err(|_| "Failed to send command completion".to_string())
+            });
+            state.text_buffer.add_line("Async task initiated.".to_string());
+        }
+        "log" => {
+            let tx = sender.clone();
+            tokio::spawn(async move {
+                if tx.send(AppMessage::NewLine(format!("[COMMAND] Log entry at {}", chrono::Local::now().format("%H:%M:%S")))).await.is_err() {
+                    eprintln!("Failed to send log message");
+                }
+            });
+        }
+        "scroll-top" => {
+            state.text_buffer.scroll_to_top();
+            state.text_buffer.add_line("Scrolled to top.".to_string());
+        }
+        "scroll-bottom" => {
+            state.text_buffer.scroll_to_bottom();
+            state.text_buffer.add_line("Scrolled to bottom.".to_string());
+        }
+        cmd if cmd.starts_with("profile ") => {
+            let name = raw_args(command, "profile ").to_string();
+            state.spawn_task(&sender, &format!("profile {}", name), open_profile(sender.clone(), name));
+        }
+        cmd if cmd.starts_with("fetch ") => {
+            match parse_fetch_args(raw_args(command, "fetch ")) {
+                Ok(request) => {
+                    let name = format!("fetch {}", request.url);
+                    let tx = sender.clone();
+                    state.spawn_task_with_progress(&sender, &name, |progress| fetch(tx, progress, request));
+                }
+                Err(e) => state.text_buffer.add_line(format!("[FETCH] {}", e)),
+            }
+        }
+        cmd if cmd.starts_with("run ") => {
+            let command_line = raw_args(command, "run ").to_string();
+            match ShellKind::from_env() {
+                Ok(shell) => {
+                    state.spawn_task(&sender, &format!("run {}", command_line), run_shell(sender.clone(), shell, command_line));
+                }
+                Err(e) => state.text_buffer.add_line(format!("[RUN] {}", e)),
+            }
+        }
+        cmd if cmd.starts_with("demo play ") => {
+            let args: Vec<&str> = raw_args(command, "demo play ").split_whitespace().collect();
+            let parsed = match args.as_slice() {
+                [path] => Some((path.to_string(), 1)),
+                [path, times] => times.parse::<usize>().ok().map(|times| (path.to_string(), times)),
+                _ => None,
+            };
+            match parsed {
+                // Also stops a script from replaying itself through `type demo play ...`
+                Some(_) if state.demo_task.is_some() => {
+                    state.text_buffer.add_line("A demo is already playing.".to_string());
+                }
+                Some((path, times)) => {
+                    state.text_buffer.add_line(format!("Playing demo '{}' ({}x)...", path, times));
+                    let demo = play_demo(sender.clone(), state.waker.clone(), path, times);
+                    state.demo_task = Some(state.spawn_task(&sender, "demo", demo));
+                }
+                None => state.text_buffer.add_line("Usage: demo play <script> [times]".to_string()),
+            }
+        }
+        cmd if cmd.starts_with("stress ") => {
+            let args: Vec<&str> = cmd.split_whitespace().collect();
+            let parsed = match args.as_slice() {
+                [_, "lines", count] => count.parse::<usize>().ok().map(|count| (count, None)),
+                [_, "lines", count, rate] => match (count.parse::<usize>(), rate.parse::<u32>()) {
+                    (Ok(count), Ok(rate)) if rate > 0 => Some((count, Some(rate))),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match parsed {
+                None => state.text_buffer.add_line("Usage: stress lines <n> [rate]".to_string()),
+                Some(_) if state.stress_task.is_some() => {
+                    state.text_buffer.add_line("A stress run is already in progress.".to_string());
+                }
+                Some((count, rate)) => {
+                    state.stress_frame_times = Some(Vec::new());
+                    state.stress_task = Some(state.spawn_task(&sender, "stress", run_stress(sender.clone(), count, rate)));
+                    match rate {
+                        Some(rate) => state.text_buffer.add_line(format!("Stress run started: {} lines at {}/s.", count, rate)),
+                        None => state.text_buffer.add_line(format!("Stress run started: {} lines, unthrottled.", count)),
+                    }
+                }
+            }
+        }
+        "session-stats" => {
+            for line in state.stats.summary() {
+                state.text_buffer.add_line(line);
+            }
+        }
+        "fullscreen" => state.window_request = Some(WindowRequest::ToggleFullscreen),
+        "minimize" => state.window_request = Some(WindowRequest::Minimize),
+        "close" | "quit" => state.request_close(),
+        "tasks" => {
+            if state.running_tasks.is_empty() {
+                state.text_buffer.add_line("No background tasks running.".to_string());
+            }
+            let tasks: Vec<String> = state
+                .running_tasks
+                .iter()
+                .map(|(id, name)| match state.task_progress.get(id) {
+                    Some(progress) => format!("  [{}] {} - {}", id, name, progress.detail),
+                    None => format!("  [{}] {}", id, name),
+                })
+                .collect();
+            for task in tasks {
+                state.text_buffer.add_line(task);
+            }
+        }
+        "touch" | "touch on" | "touch off" => {
+            state.touch.enabled = match cmd.as_str() {
+                "touch" => !state.touch.enabled,
+                _ => cmd.ends_with("on"),
+            };
+            state.touch.chosen = true;
+            state.text_buffer.add_line(format!("Touch mode: {}", if state.touch.enabled { "on" } else { "off" }));
+        }
+        "remote" | "remote on" | "remote off" => {
+            state.remote.enabled = match cmd.as_str() {
+                "remote" => !state.remote.enabled,
+                _ => cmd.ends_with("on"),
+            };
+            state.text_buffer.add_line(format!("Remote navigation: {}", if state.remote.enabled { "on" } else { "off" }));
+            if state.remote.enabled {
+                state.text_buffer.add_line("  ▲/▼ scroll, PgUp/PgDn page, Home/End jump, ◀/▶ pick a pin, Enter/F5 run it".to_string());
+                if !state.remote.gamepads_opened {
+                    state.remote.gamepads_opened = true;
+                    let opened = open_gamepads(&sender, &state.waker);
+                    state.text_buffer.add_line(format!("  Gamepads found: {}", opened));
+                }
+            }
+        }
+        cmd if cmd.starts_with("pin ") => {
+            let pinned = raw_args(command, "pin ").to_string();
+            state.text_buffer.add_line(format!("Pinned #{}: {}", state.pinned_commands.len() + 1, pinned));
+            state.pinned_commands.push(pinned);
+        }
+        cmd if cmd.starts_with("unpin ") => {
+            match cmd[6..].trim().parse::<usize>() {
+                Ok(n) if (1..=state.pinned_commands.len()).contains(&n) => {
+                    let removed = state.pinned_commands.remove(n - 1);
+                    state.remote.selected = state.remote.selected.min(state.pinned_commands.len().saturating_sub(1));
+                    state.text_buffer.add_line(format!("Unpinned: {}", removed));
+                }
+                _ => state.text_buffer.add_line(format!("No pinned command #{}. Use 'pins' to list them.", cmd[6..].trim())),
+            }
+        }
+        "pins" => {
+            if state.pinned_commands.is_empty() {
+                state.text_buffer.add_line("No pinned commands. Use 'pin <command>' to add one.".to_string());
+            }
+            for (index, pinned) in state.pinned_commands.iter().enumerate() {
+                state.text_buffer.add_line(format!("  #{} {}", index + 1, pinned));
+            }
+        }
+        "confirm-close on" | "confirm-close off" => {
+            state.confirm_close = cmd.ends_with("on");
+            state.text_buffer.add_line(format!("Confirm on close: {}", if state.confirm_close { "on" } else { "off" }));
+        }
+        "ai" => {
+            state.ai_panel.open = !state.ai_panel.open;
+            state.text_buffer.add_line(format!("AI panel: {}", if state.ai_panel.open { "open" } else { "closed" }));
+        }
+        "paste-image" => {
+            state.ai_panel.open = true;
+            state.spawn_task(&sender, "paste-image", paste_image(sender.clone()));
+            state.text_buffer.add_line("Reading an image from the clipboard...".to_string());
+        }
+        "compare" => {
+            if state.compare.take().is_some() {
+                state.text_buffer.add_line("Compare mode off.".to_string());
+            } else {
+                state.compare = Some(CompareState::default());
+                state.text_buffer.add_line("Compare mode on. Click a line in each pane, shift-click to extend.".to_string());
+            }
+        }
+        cmd if cmd.starts_with("focus-follows") => {
+            let mode = match cmd["focus-follows".len()..].trim() {
+                "off" => Some(AttentionMode::Off),
+                "badge" => Some(AttentionMode::Badge),
+                "focus" => Some(AttentionMode::Focus),
+                "" => {
+                    state.text_buffer.add_line(format!("Focus-follows-output: {:?}", state.attention_mode));
+                    None
+                }
+                other => {
+                    state.text_buffer.add_line(format!("Unknown mode: '{}'. Use off, badge or focus.", other));
+                    None
+                }
+            };
+            if let Some(mode) = mode {
+                state.attention_mode = mode;
+                state.text_buffer.add_line(format!("Focus-follows-output set to {:?}.", mode));
+            }
+        }
+        cmd if cmd.starts_with("dialog ") => {
+            let args = raw_args(command, "dialog ");
+            let (kind, text) = args.split_once(' ').unwrap_or((args, ""));
+            let kind = kind.to_lowercase();
+            let text = text.trim().to_string();
+            let dialogs = DialogApi {
+                sender: sender.clone(),
+                waker: state.waker.clone(),
+            };
+            let tx = sender.clone();
+            state.spawn_task(&sender, &format!("dialog {}", kind), async move {
+                let result = match kind.as_str() {
+                    "message" => {
+                        dialogs.message("MESSAGE", &text).await;
+                        "Message acknowledged.".to_string()
+                    }
+                    "confirm" => format!("Confirmed: {}", dialogs.confirm("CONFIRM", &text).await),
+                    "input" => match dialogs.input("INPUT", &text, "").await {
+                        Some(value) => format!("Input: {}", value),
+                        None => "Input cancelled.".to_string(),
+                    },
+                    "select" => {
+                        let options = text.split(',').map(|o| o.trim().to_string()).filter(|o| !o.is_empty()).collect();
+                        match dialogs.select("SELECT", "Choose an option:", options).await {
+                            Some(choice) => format!("Selected: {}", choice),
+                            None => "Selection cancelled.".to_string(),
+                        }
+                    }
+                    _ => format!("Unknown dialog kind: '{}'. Use message, confirm, input or select.", kind),
+                };
+                tx.send(AppMessage::NewLine(format!("[DIALOG] {}", result)))
+                    .await
+                    .map_err(|_| "Failed to send dialog result".to_string())
+            });
+        }
+        "new-window" => match std::env::current_exe().and_then(|exe| tokio::process::Command::new(exe).spawn()) {
+            Ok(mut child) => {
+                // Reap the new window's process when it exits
+                tokio::spawn(async move {
+                    let _ = child.wait().await;
+                });
+                state.text_buffer.add_line("Opened a new window.".to_string());
+            }
+            Err(e) => state.text_buffer.add_line(format!("Failed to open a new window: {}", e)),
+        },
+        "" => {
+            // Empty command, do nothing
+        }
+        _ => {
+            state.text_buffer.add_line(format!("Unknown command: '{}'. Type 'help' for available commands.", cmd));
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = LaunchOptions::from_args()?;
+    match RendererKind::from_env()? {
+        RendererKind::Wgpu => NeoTermApp::new(options).await?.run(),
+        RendererKind::Null => run_headless(options).await,
+    }
+}
+
+fn draw_ui(ctx: &Context, state: &mut AppState, sender: mpsc::Sender<AppMessage>) {
+    for spec in COMMANDS {
+        if let Some(shortcut) = &spec.shortcut {
+            if ctx.input_mut(|i| i.consume_shortcut(shortcut)) {
+                submit_command(spec.usage, state, sender.clone());
+            }
+        }
+    }
+
+    if state.touch.styled != state.touch.enabled {
+        let mut style = create_hacker_theme();
+        if state.touch.enabled {
+            apply_touch_spacing(&mut style);
+        }
+        ctx.set_style(style);
+        state.touch.styled = state.touch.enabled;
+    }
+
+    // Remote keys only apply when nothing has keyboard focus, so typing is unaffected
+    if state.remote.enabled && ctx.memory(|memory| memory.focused().is_none()) {
+        handle_remote_keys(ctx, state, &sender);
+    }
+
+    if state.launcher.is_some() {
+        draw_launcher(ctx, state, sender);
+        return;
+    }
+
+    // macOS gets the native menu bar instead
+    if !cfg!(target_os = "macos") {
+        draw_menu_bar(ctx, state, &sender);
+    }
+
+    if state.ai_panel.open {
+        draw_ai_panel(ctx, state, &sender);
+    }
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.heading("SYSTEM CONSOLE");
+        ui.separator();
+
+        draw_timeline(ui, state);
+
+        if state.remote.enabled {
+            draw_pinned_commands(ui, state, &sender);
+        }
+
+        let text_frame = egui::Frame::dark_canvas(ui.style());
+        text_frame.show(ui, |ui| {
+            if state.compare.is_some() {
+                draw_compare(ui, state);
+                return;
+            }
+            let mut console = ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .stick_to_bottom(!state.text_buffer.is_at_bottom());
+            if let Some(offset) = state.remote.scroll_to.take() {
+                console = console.vertical_scroll_offset(offset);
+            }
+            state.remote.row_height = ui.text_style_height(&TextStyle::Body) + ui.spacing().item_spacing.y;
+            let output = console.show(ui, |ui| {
+                    ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
+                        for (index, line) in state.text_buffer.visible_lines().iter().enumerate() {
+                            let text = match state.text_buffer.severity(state.text_buffer.visible_line_number(index)) {
+                                Some(severity) => egui::RichText::new(line).color(severity_color(severity)),
+                                None => egui::RichText::new(line),
+                            };
+                            // Click sense gives the line a right-click and long-press menu
+                            let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+                            response.context_menu(|ui| {
+                                if ui.button("Copy line").clicked() {
+                                    ui.ctx().output_mut(|output| output.copied_text = line.clone());
+                                    ui.close_menu();
+                                }
+                                if ui.button("Edit as command").clicked() {
+                                    state.command_input = line.strip_prefix("> ").unwrap_or(line).to_string();
+                                    ui.close_menu();
+                                }
+                            });
+                            if state.scroll_to_line == Some(state.text_buffer.visible_line_number(index)) {
+                                response.scroll_to_me(Some(egui::Align::Center));
+                                state.scroll_to_line = None;
+                            }
+                        }
+                    });
+                    ui.allocate_space(ui.available_size());
+                });
+            state.remote.offset = output.state.offset.y;
+        });
+
+        ui.add_space(8.0);
+
+        ui.vertical(|ui| {
+            ui.heading("ASYNC_TASK_MODULE");
+            if ui.button("> EXECUTE_SLOW_TASK (2 seconds)").clicked() {
+                let tx = sender.clone();
+                state.spawn_task(&sender, "slow-task", async move {
+                    if tx.send(AppMessage::NewLine("[ASYNC] Task started...".to_string())).await.is_err() {
+                        return Err("Failed to send async task start message".to_string());
+                    }
+                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    tx.send(AppMessage::TaskCompleted("Task completed successfully.".to_string()))
+                        .await
+                        .map_err(|_| "Failed to send task completion message".to_string())
+                });
+            }
+            if ui.button("> GENERATE LOG LINE").clicked() {
+                let tx = sender.clone();
+                tokio::spawn(async move {
+                    if tx.send(AppMessage::NewLine(format!("[LOG] Sample log entry at {}", chrono::Local::now().format("%H:%M:%S")))).await.is_err() {
+                        eprintln!("Failed to send log message");
+                    }
+                });
+            }
+        });
+        ui.add_space(8.0);
+
+        // Command input section
+        ui.horizontal(|ui| {
+            ui.heading("COMMAND INPUT");
+            ui.add_space(8.0);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(">");
+            let response = ui.add(egui::TextEdit::singleline(&mut state.command_input).id(egui::Id::new(COMMAND_INPUT_ID)));
+            sync_primary_selection(ui, &response, state);
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if (submitted || ui.button("Execute").clicked()) && !state.command_input.trim().is_empty() {
+                let command = std::mem::take(&mut state.command_input);
+                submit_command(&command, state, sender.clone());
+            }
+        });
+
+        if state.touch.enabled {
+            draw_touch_keys(ui, &mut state.touch);
+        }
+
+        // Scroll controls
+        ui.horizontal(|ui| {
+            ui.heading("SCROLL CONTROLS");
+            if ui.button("↑ Top").clicked() {
+                state.text_buffer.scroll_to_top();
+            }
+            if ui.button("↑ Up").clicked() {
+                state.text_buffer.scroll_up();
+            }
+            if ui.button("↓ Down").clicked() {
+                state.text_buffer.scroll_down();
+            }
+            if ui.button("↓ Bottom").clicked() {
+                state.text_buffer.scroll_to_bottom();
+            }
+
+            let total_lines = state.text_buffer.lines.len();
+            let visible_lines = state.text_buffer.max_lines;
+            let scroll_pos = state.text_buffer.scroll_position;
+
+            if total_lines > visible_lines {
+                let percentage = if total_lines > 0 {
+                    ((scroll_pos as f32) / (total_lines - visible_lines) as f32 * 100.0) as usize
+                } else {
+                    0
+                };
+                ui.label(format!("Position: {}% ({}/{})",
+                    percentage, scroll_pos + 1, total_lines));
+            } else {
+                ui.label(format!("Lines: {}/{}", total_lines, visible_lines));
+            }
+        });
+
+        ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
+            ui.separator();
+            for (id, progress) in &state.task_progress {
+                let name = state.running_tasks.get(id).map(String::as_str).unwrap_or_default();
+                let text = format!("{} - {}", name, progress.detail);
+                match progress.fraction {
+                    Some(fraction) => {
+                        ui.add(egui::ProgressBar::new(fraction).text(text));
+                    }
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label(text);
+                        });
+                    }
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.label(&state.status_message);
+                if let Some(last_error) = &state.last_error {
+                    let badge = format!("[{} NEW ERR] {}", state.unseen_errors, last_error);
+                    ui.colored_label(severity_color(Severity::Error), badge);
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let meter = &state.throughput;
+                    let frame_ms = meter.frame_ms.back().copied().unwrap_or_default();
+                    ui.label(format!("{:>5.1} ms", frame_ms));
+                    sparkline(ui, meter.frame_ms.iter().copied(), severity_color(Severity::Warning))
+                        .on_hover_text(format!(
+                            "Slowest frame per second, last {}s (peak {:.1} ms)",
+                            THROUGHPUT_HISTORY,
+                            meter.frame_ms.iter().copied().fold(0.0, f32::max)
+                        ));
+                    ui.separator();
+                    let messages = meter.messages.back().copied().unwrap_or_default();
+                    ui.label(format!("{:>5} msg/s", messages));
+                    sparkline(ui, meter.messages.iter().map(|&count| count as f32), Color32::from_rgb(0, 255, 68))
+                        .on_hover_text(format!(
+                            "Messages received per second, last {}s (peak {})",
+                            THROUGHPUT_HISTORY,
+                            meter.messages.iter().max().copied().unwrap_or_default()
+                        ));
+                });
+            });
+        });
+    });
+
+    draw_dialogs(ctx, state);
+    draw_close_prompt(ctx, state);
+}
+
+/// Draws the on-screen key row. Taps become real key events on the next
+/// frame, aimed at the command input, so they reach whatever handles keys.
+fn draw_touch_keys(ui: &mut egui::Ui, touch: &mut TouchState) {
+    ui.horizontal(|ui| {
+        let mut tapped = None;
+        if ui.button("Esc").clicked() {
+            tapped = Some(Key::Escape);
+        }
+        if ui.button("Tab").clicked() {
+            tapped = Some(Key::Tab);
+        }
+        if ui.selectable_label(touch.ctrl_latched, "Ctrl").clicked() {
+            touch.ctrl_latched = !touch.ctrl_latched;
+        }
+        for (label, key) in TOUCH_ARROW_KEYS {
+            if ui.button(*label).clicked() {
+                tapped = Some(*key);
+            }
+        }
+
+        if let Some(key) = tapped {
+            let modifiers = if std::mem::take(&mut touch.ctrl_latched) { Modifiers::CTRL } else { Modifiers::NONE };
+            for pressed in [true, false] {
+                touch.pending_keys.push(egui::Event::Key { key, physical_key: None, pressed, repeat: false, modifiers });
+            }
+            ui.memory_mut(|memory| memory.request_focus(egui::Id::new(COMMAND_INPUT_ID)));
+            ui.ctx().request_repaint();
+        }
+    });
+}
+
+/// Maps remote-style keys to remote actions.
+fn handle_remote_keys(ctx: &Context, state: &mut AppState, sender: &mpsc::Sender<AppMessage>) {
+    let keys = [
+        (Key::ArrowUp, RemoteAction::Scroll(-1)),
+        (Key::ArrowDown, RemoteAction::Scroll(1)),
+        (Key::PageUp, RemoteAction::Scroll(-REMOTE_PAGE_LINES)),
+        (Key::PageDown, RemoteAction::Scroll(REMOTE_PAGE_LINES)),
+        (Key::Home, RemoteAction::ScrollTop),
+        (Key::End, RemoteAction::ScrollBottom),
+        (Key::ArrowLeft, RemoteAction::PreviousPin),
+        (Key::ArrowRight, RemoteAction::NextPin),
+        (Key::Enter, RemoteAction::RunPin),
+        (Key::F5, RemoteAction::RunPin),
+    ];
+    for (key, action) in keys {
+        if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, key)) {
+            apply_remote_action(state, sender, action);
+        }
+    }
+}
+
+/// Scrolls the console, or picks or runs a pinned command.
+fn apply_remote_action(state: &mut AppState, sender: &mpsc::Sender<AppMessage>, action: RemoteAction) {
+    let remote = &mut state.remote;
+    let pins = state.pinned_commands.len();
+    match action {
+        RemoteAction::Scroll(lines) => {
+            // Several presses can land before the next frame applies the first
+            let from = remote.scroll_to.unwrap_or(remote.offset);
+            remote.scroll_to = Some((from + lines as f32 * remote.row_height).max(0.0));
+        }
+        RemoteAction::ScrollTop => remote.scroll_to = Some(0.0),
+        // The scroll area clamps this to the end of the content
+        RemoteAction::ScrollBottom => remote.scroll_to = Some(f32::MAX),
+        RemoteAction::PreviousPin if pins > 0 => remote.selected = (remote.selected + pins - 1) % pins,
+        RemoteAction::NextPin if pins > 0 => remote.selected = (remote.selected + 1) % pins,
+        RemoteAction::RunPin if pins > 0 => {
+            let command = state.pinned_commands[remote.selected].clone();
+            submit_command(&command, state, sender.clone());
+        }
+        _ => {}
+    }
+}
+
+/// Shows the pinned commands in large type, highlighting the one Enter would run.
+fn draw_pinned_commands(ui: &mut egui::Ui, state: &mut AppState, sender: &mpsc::Sender<AppMessage>) {
+    let mut clicked = None;
+    ui.horizontal_wrapped(|ui| {
+        ui.label(egui::RichText::new("PINNED").heading());
+        if state.pinned_commands.is_empty() {
+            ui.label("none yet - use 'pin <command>'");
+        }
+        for (index, pinned) in state.pinned_commands.iter().enumerate() {
+            let text = egui::RichText::new(format!("{} {}", index + 1, pinned)).heading();
+            if ui.selectable_label(index == state.remote.selected, text).clicked() {
+                clicked = Some(index);
+            }
+        }
+    });
+    ui.separator();
+
+    if let Some(index) = clicked {
+        state.remote.selected = index;
+        let command = state.pinned_commands[index].clone();
+        submit_command(&command, state, sender.clone());
+    }
+}
+
+/// Asks whether to quit while background tasks are still running.
+fn draw_close_prompt(ctx: &Context, state: &mut AppState) {
+    if !state.close_prompt_open {
+        return;
+    }
+    // Everything may have finished while the prompt was open
+    if state.running_tasks.is_empty() {
+        state.close_prompt_open = false;
+        state.window_request = Some(WindowRequest::Quit);
+        return;
+    }
+
+    egui::Window::new("CONFIRM EXIT")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label("The following will be terminated:");
+            for name in state.running_tasks.values() {
+                ui.colored_label(severity_color(Severity::Warning), format!("  - task: {}", name));
+            }
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                if ui.button("> QUIT ANYWAY").clicked() {
+                    state.window_request = Some(WindowRequest::Quit);
+                }
+                if ui.button("> MINIMIZE INSTEAD").clicked() {
+                    state.window_request = Some(WindowRequest::Minimize);
+                }
+                if ui.button("> CANCEL").clicked() {
+                    state.close_prompt_open = false;
+                }
+            });
+        });
+
+    if state.window_request.is_some() {
+        state.close_prompt_open = false;
+    }
+}
+
+/// Draws the oldest pending script dialog and sends back the answer once the user responds.
+fn draw_dialogs(ctx: &Context, state: &mut AppState) {
+    let Some(dialog) = state.dialogs.front_mut() else {
+        return;
+    };
+
+    let mut answer = None;
+    egui::Window::new(dialog.request.title.as_str())
+        .id(egui::Id::new("script_dialog"))
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.label(&dialog.request.text);
+            ui.add_space(8.0);
+
+            match &dialog.request.kind {
+                DialogKind::Message => {
+                    if ui.button("> OK").clicked() {
+                        answer = Some(DialogResponse::Dismissed);
+                    }
+                }
+                DialogKind::Confirm => {
+                    ui.horizontal(|ui| {
+                        if ui.button("> YES").clicked() {
+                            answer = Some(DialogResponse::Confirmed(true));
+                        }
+                        if ui.button("> NO").clicked() {
+                            answer = Some(DialogResponse::Confirmed(false));
+                        }
+                    });
+                }
+                DialogKind::Input { .. } => {
+                    let response = ui.text_edit_singleline(&mut dialog.input);
+                    if !response.has_focus() && !response.lost_focus() {
+                        response.request_focus();
+                    }
+                    let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.horizontal(|ui| {
+                        if submitted || ui.button("> OK").clicked() {
+                            answer = Some(DialogResponse::Input(Some(dialog.input.clone())));
+                        }
+                        if ui.button("> CANCEL").clicked() {
+                            answer = Some(DialogResponse::Input(None));
+                        }
+                    });
+                }
+                DialogKind::Select { options } => {
+                    for (index, option) in options.iter().enumerate() {
+                        ui.selectable_value(&mut dialog.selected, index, option);
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("> OK").clicked() {
+                            answer = Some(DialogResponse::Selected(options.get(dialog.selected).cloned()));
+                        }
+                        if ui.button("> CANCEL").clicked() {
+                            answer = Some(DialogResponse::Selected(None));
+                        }
+                    });
+                }
+            }
+        });
+
+    if let Some(answer) = answer {
+        if let Some(dialog) = state.dialogs.pop_front() {
+            // The script may have given up waiting, which is fine
+            let _ = dialog.request.respond_to.send(answer);
+        }
+    }
+}
+
+/// Marker colour for each kind of timeline event.
+fn timeline_color(kind: TimelineKind) -> Color32 {
+    match kind {
+        TimelineKind::Command => Color32::from_rgb(0, 255, 68),
+        TimelineKind::Error => severity_color(Severity::Error),
+        TimelineKind::TaskCompleted => Color32::from_rgb(0, 180, 255),
+    }
+}
+
+/// Draws the buffer's activity timeline as a strip of markers. Clicking a marker
+/// scrolls the console to the line it was recorded on.
+fn draw_timeline(ui: &mut egui::Ui, state: &mut AppState) {
+    let (rect, response) = ui.allocate_exact_size(Vec2::new(ui.available_width(), 18.0), egui::Sense::click());
+    let painter = ui.painter_at(rect);
+    painter.rect_stroke(rect, Rounding::ZERO, Stroke::new(1.0, Color32::from_rgb(60, 60, 60)));
+
+    let buffer = &state.text_buffer;
+    let start = buffer.created.timestamp_millis();
+    let span = (chrono::Local::now().timestamp_millis() - start).max(1) as f32;
+    let marker_x = |event: &TimelineEvent| {
+        let t = (event.time.timestamp_millis() - start) as f32 / span;
+        rect.left() + 4.0 + t.clamp(0.0, 1.0) * (rect.width() - 8.0)
+    };
+
+    for event in &buffer.timeline {
+        let x = marker_x(event);
+        painter.line_segment(
+            [egui::pos2(x, rect.top() + 3.0), egui::pos2(x, rect.bottom() - 3.0)],
+            Stroke::new(2.0, timeline_color(event.kind)),
+        );
+    }
+
+    // Markers are only a couple of pixels wide, so pick the closest one near the pointer
+    let nearest = |pos: egui::Pos2| {
+        buffer
+            .timeline
+            .iter()
+            .map(|event| ((marker_x(event) - pos.x).abs(), event))
+            .filter(|(distance, _)| *distance <= 6.0)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, event)| event)
+    };
+
+    let hovered = response.hover_pos().and_then(nearest).map(|event| {
+        format!("{} {:?}: {}", event.time.format("%H:%M:%S"), event.kind, event.label)
+    });
+    let clicked = response
+        .interact_pointer_pos()
+        .filter(|_| response.clicked())
+        .and_then(nearest)
+        .map(|event| event.line_number);
+
+    if let Some(line_number) = clicked {
+        state.scroll_to_line = Some(line_number);
+    }
+    if let Some(text) = hovered {
+        response.on_hover_text(text);
+    }
+}
+
+/// Draws the two-pane compare view, highlighting lines that differ between the selections.
+fn draw_compare(ui: &mut egui::Ui, state: &mut AppState) {
+    let Some(compare) = state.compare.as_mut() else {
+        return;
+    };
+    compare.update_diff(&state.text_buffer);
+
+    let mut clicked = None;
+    ui.columns(2, |columns| {
+        for (ui, side) in columns.iter_mut().zip([CompareSide::Left, CompareSide::Right]) {
+            let selection = compare.selection(side);
+            let differs = compare.diff.as_ref().map(|(left, right)| match side {
+                CompareSide::Left => left,
+                CompareSide::Right => right,
+            });
+            let changed_color = match side {
+                CompareSide::Left => Color32::from_rgb(110, 20, 20),
+                CompareSide::Right => Color32::from_rgb(20, 60, 110),
+            };
+
+            ScrollArea::vertical()
+                .id_source(side)
+                .auto_shrink([false, false])
+                .max_height(ui.available_height() - 24.0)
+                .show(ui, |ui| {
+                    for (index, line) in state.text_buffer.visible_lines().iter().enumerate() {
+                        let number = state.text_buffer.visible_line_number(index);
+                        let selected = selection.is_some_and(|(start, end)| (start..=end).contains(&number));
+                        let mut text = egui::RichText::new(format!("{:>5} {}", number + 1, line));
+                        let differs = selection
+                            .filter(|_| selected)
+                            .and_then(|(start, _)| differs?.get(number - start).copied());
+                        if differs == Some(true) {
+                            text = text.background_color(changed_color);
+                        }
+                        if ui.selectable_label(selected, text).clicked() {
+                            clicked = Some((side, number, ui.input(|i| i.modifiers.shift)));
+                        }
+                    }
+                });
+
+            let summary = match (selection, differs) {
+                (Some((start, end)), Some(differs)) => format!(
+                    "Lines {}-{}: {} differ",
+                    start + 1,
+                    end + 1,
+                    differs.iter().filter(|d| **d).count()
+                ),
+                (Some((start, end)), None) => format!("Lines {}-{}", start + 1, end + 1),
+                (None, _) => "No selection".to_string(),
+            };
+            ui.label(summary);
+        }
+    });
+
+    if let Some((side, number, extend)) = clicked {
+        compare.select(side, number, extend);
+    }
+}
+
+/// Offers the command input's selected text as the primary selection and pastes the
+/// primary selection into the input on middle-click.
+fn sync_primary_selection(ui: &egui::Ui, response: &egui::Response, state: &mut AppState) {
+    let Some(primary) = state.primary_selection.as_mut() else {
+        return;
+    };
+
+    let selection = egui::text_edit::TextEditState::load(ui.ctx(), response.id).and_then(|edit| edit.cursor.char_range());
+    if let Some(range) = selection {
+        let [start, end] = range.sorted();
+        if start.index != end.index {
+            let selected: String = state.command_input.chars().skip(start.index).take(end.index - start.index).collect();
+            primary.store(&selected);
+        }
+    }
+
+    if response.middle_clicked() {
+        if let Some(text) = primary.load() {
+            state.command_input.push_str(&text);
+        }
+    }
+}
+
+/// Menu bar menus in display order, with their titles.
+const MENU_GROUPS: [(MenuGroup, &str); 4] = [
+    (MenuGroup::App, "Neo-Term"),
+    (MenuGroup::Edit, "Edit"),
+    (MenuGroup::View, "View"),
+    (MenuGroup::Window, "Window"),
+];
+
+/// Draws the AI side panel, which has to be added before the central panel.
+fn draw_ai_panel(ctx: &Context, state: &mut AppState, sender: &mpsc::Sender<AppMessage>) {
+    let mut paste = false;
+    egui::SidePanel::right("ai_panel").resizable(true).default_width(320.0).show(ctx, |ui| {
+        ui.heading("AI");
+        ui.label(egui::RichText::new("No model is connected; the prompt and images stay on this machine.").weak());
+        ui.separator();
+
+        let panel = &mut state.ai_panel;
+        ui.add(
+            egui::TextEdit::multiline(&mut panel.prompt)
+                .hint_text("Ask about the output...")
+                .desired_rows(4)
+                .desired_width(f32::INFINITY),
+        );
+        paste = ui.button("Paste Image").clicked();
+        ui.separator();
+
+        let mut removed = None;
+        ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+            for (index, item) in panel.attachments.iter_mut().enumerate() {
+                let texture = item.texture.get_or_insert_with(|| {
+                    let image = &item.attachment.image;
+                    let size = [image.width as usize, image.height as usize];
+                    let pixels = egui::ColorImage::from_rgba_unmultiplied(size, &image.pixels);
+                    ui.ctx().load_texture(format!("ai_attachment_{}", index), pixels, Default::default())
+                });
+                let size = texture.size_vec2();
+                ui.image((texture.id(), size * (ui.available_width() / size.x).min(1.0)));
+                ui.horizontal(|ui| {
+                    ui.label(item.attachment.summary());
+                    if ui.small_button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+                ui.separator();
+            }
+        });
+        if let Some(index) = removed {
+            panel.attachments.remove(index);
+        }
+    });
+
+    if paste {
+        submit_command("paste-image", state, sender.clone());
+    }
+}
+
+/// Draws the App/Edit/View/Window menu bar from the command registry.
+fn draw_menu_bar(ctx: &Context, state: &mut AppState, sender: &mpsc::Sender<AppMessage>) {
+    let mut chosen = None;
+    egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+        egui::menu::bar(ui, |ui| {
+            for (group, title) in MENU_GROUPS {
+                ui.menu_button(title, |ui| {
+                    for spec in COMMANDS {
+                        let Some((_, label)) = spec.menu.filter(|(g, _)| *g == group) else {
+                            continue;
+                        };
+                        let mut button = egui::Button::new(label);
+                        if let Some(shortcut) = &spec.shortcut {
+                            button = button.shortcut_text(ctx.format_shortcut(shortcut));
+                        }
+                        if ui.add(button).clicked() {
+                            chosen = Some(spec.usage);
+                            ui.close_menu();
+                        }
+                    }
+                });
+            }
+        });
+    });
+
+    if let Some(command) = chosen {
+        submit_command(command, state, sender.clone());
+    }
+}
+
+/// The native macOS menu bar, built from the command registry with `objc2-app-kit`.
+/// Chosen items run through `AppMessage::RunCommand`; the standard Copy, Paste and
+/// Select All items reach egui as `AppMessage::Input`.
+#[cfg(target_os = "macos")]
+mod native_menu {
+    use super::{AppMessage, MenuGroup, UiWaker, COMMANDS, MENU_GROUPS};
+    use egui::{Key, KeyboardShortcut, Modifiers};
+    use objc2::rc::Retained;
+    use objc2::runtime::{AnyObject, NSObject, Sel};
+    use objc2::{define_class, msg_send, sel, DefinedClass, MainThreadMarker, MainThreadOnly};
+    use objc2_app_kit::{NSApplication, NSEventModifierFlags, NSMenu, NSMenuItem, NSPasteboard, NSPasteboardTypeString};
+    use objc2_foundation::NSString;
+    use std::cell::OnceCell;
+    use tokio::sync::mpsc;
+
+    const NS_SHIFT: usize = 1 << 17;
+    const NS_CONTROL: usize = 1 << 18;
+    const NS_OPTION: usize = 1 << 19;
+    const NS_COMMAND: usize = 1 << 20;
+
+    /// Where `MenuTarget` delivers actions.
+    struct Ivars {
+        sender: mpsc::Sender<AppMessage>,
+        waker: UiWaker,
+    }
+
+    define_class!(
+        /// Receives the actions of every item in the menu bar.
+        // SAFETY: NSObject has no subclassing requirements and MenuTarget does not implement Drop.
+        #[unsafe(super(NSObject))]
+        #[thread_kind = MainThreadOnly]
+        #[name = "NeoTermMenuTarget"]
+        #[ivars = Ivars]
+        struct MenuTarget;
+
+        impl MenuTarget {
+            /// A registry item: its tag is its index in `COMMANDS`.
+            #[unsafe(method(neoTermCommand:))]
+            fn command(&self, item: &NSMenuItem) {
+                let tag: isize = unsafe { msg_send![item, tag] };
+                if let Some(spec) = usize::try_from(tag).ok().and_then(|index| COMMANDS.get(index)) {
+                    self.send(AppMessage::RunCommand(spec.usage.to_string()));
+                }
+            }
+
+            #[unsafe(method(copy:))]
+            fn copy(&self, _sender: Option<&AnyObject>) {
+                self.send(AppMessage::Input(egui::Event::Copy));
+            }
+
+            #[unsafe(method(paste:))]
+            fn paste(&self, _sender: Option<&AnyObject>) {
+                let text = unsafe { NSPasteboard::generalPasteboard().stringForType(NSPasteboardTypeString) };
+                if let Some(text) = text {
+                    self.send(AppMessage::Input(egui::Event::Paste(text.to_string())));
+                }
+            }
+
+            #[unsafe(method(selectAll:))]
+            fn select_all(&self, _sender: Option<&AnyObject>) {
+                let modifiers = Modifiers::COMMAND;
+                self.send(AppMessage::Input(egui::Event::Key { key: Key::A, physical_key: None, pressed: true, repeat: false, modifiers }));
+            }
+        }
+    );
+
+    impl MenuTarget {
+        fn new(mtm: MainThreadMarker, sender: mpsc::Sender<AppMessage>, waker: UiWaker) -> Retained<Self> {
+            let this = Self::alloc(mtm).set_ivars(Ivars { sender, waker });
+            unsafe { msg_send![super(this), init] }
+        }
+
+        fn send(&self, message: AppMessage) {
+            let ivars = self.ivars();
+            if ivars.sender.try_send(message).is_ok() {
+                ivars.waker.wake();
+            }
+        }
+    }
+
+    thread_local! {
+        /// Menu items do not retain their target, so it is kept here for the life of the app.
+        static TARGET: OnceCell<Retained<MenuTarget>> = const { OnceCell::new() };
+    }
+
+    /// The key equivalent and modifier mask for a shortcut. AppKit wants the character the
+    /// key types, so only single-character keys (letters and digits) get one.
+    fn key_equivalent(shortcut: Option<KeyboardShortcut>) -> (String, usize) {
+        let Some(KeyboardShortcut { modifiers, logical_key }) = shortcut else {
+            return (String::new(), 0);
+        };
+        let name = logical_key.name();
+        if name.chars().count() != 1 {
+            return (String::new(), 0);
+        }
+        let mut mask = 0;
+        if modifiers.command || modifiers.mac_cmd {
+            mask |= NS_COMMAND;
+        }
+        if modifiers.ctrl {
+            mask |= NS_CONTROL;
+        }
+        if modifiers.alt {
+            mask |= NS_OPTION;
+        }
+        if modifiers.shift {
+            mask |= NS_SHIFT;
+        }
+        (name.to_lowercase(), mask)
+    }
+
+    /// Appends an item that sends `action` to `target`.
+    ///
+    /// # Safety
+    /// `target` must implement `action`.
+    unsafe fn add_item(
+        mtm: MainThreadMarker,
+        menu: &NSMenu,
+        target: &MenuTarget,
+        label: &str,
+        action: Sel,
+        (key, mask): (String, usize),
+    ) -> Retained<NSMenuItem> {
+        let item = NSMenuItem::initWithTitle_action_keyEquivalent(
+            NSMenuItem::alloc(mtm),
+            &NSString::from_str(label),
+            Some(action),
+            &NSString::from_str(&key),
+        );
+        item.setKeyEquivalentModifierMask(NSEventModifierFlags(mask));
+        item.setTarget(Some(target));
+        menu.addItem(&item);
+        item
+    }
+
+    /// Replaces winit's default menu with one built from `MENU_GROUPS` and `COMMANDS`.
+    /// Only the first call has any effect.
+    pub fn install(sender: mpsc::Sender<AppMessage>, waker: UiWaker) {
+        let Some(mtm) = MainThreadMarker::new() else {
+            return;
+        };
+        if TARGET.with(|target| target.get().is_some()) {
+            return;
+        }
+        let target = MenuTarget::new(mtm, sender, waker);
+
+        // SAFETY: MenuTarget implements every action used here, and TARGET keeps it alive
+        unsafe {
+            let app = NSApplication::sharedApplication(mtm);
+            let menu_bar = NSMenu::new(mtm);
+            for (group, title) in MENU_GROUPS {
+                let menu = NSMenu::initWithTitle(NSMenu::alloc(mtm), &NSString::from_str(title));
+                if group == MenuGroup::Edit {
+                    let command = |key: &str| (key.to_string(), NS_COMMAND);
+                    add_item(mtm, &menu, &target, "Copy", sel!(copy:), command("c"));
+                    add_item(mtm, &menu, &target, "Paste", sel!(paste:), command("v"));
+                    add_item(mtm, &menu, &target, "Select All", sel!(selectAll:), command("a"));
+                    menu.addItem(&NSMenuItem::separatorItem(mtm));
+                }
+                for (index, spec) in COMMANDS.iter().enumerate() {
+                    let Some((_, label)) = spec.menu.filter(|(g, _)| *g == group) else {
+                        continue;
+                    };
+                    let item = add_item(mtm, &menu, &target, label, sel!(neoTermCommand:), key_equivalent(spec.shortcut));
+                    item.setTag(index as isize);
+                }
+
+                let holder = NSMenuItem::new(mtm);
+                holder.setSubmenu(Some(&menu));
+                menu_bar.addItem(&holder);
+                if group == MenuGroup::Window {
+                    // Lets AppKit list open windows and add its own window items here
+                    app.setWindowsMenu(Some(&menu));
+                }
+            }
+            app.setMainMenu(Some(&menu_bar));
+        }
+        TARGET.with(|cell| {
+            let _ = cell.set(target);
+        });
+    }
+}
+
+/// Draws launcher mode: the command palette until a command runs, then its output
+/// until the user dismisses the window with Enter or Esc.
+fn draw_launcher(ctx: &Context, state: &mut AppState, sender: mpsc::Sender<AppMessage>) {
+    let Some(mut launcher) = state.launcher.take() else {
+        return;
+    };
+    let (enter, escape) = ctx.input(|i| (i.key_pressed(Key::Enter), i.key_pressed(Key::Escape)));
+
+    egui::CentralPanel::default().show(ctx, |ui| match launcher.output_start {
+        None => {
+            let mut command = None;
+            ui.horizontal(|ui| {
+                ui.label(">");
+                let response = ui.add(egui::TextEdit::singleline(&mut state.command_input).desired_width(f32::INFINITY));
+                response.request_focus();
+            });
+            ui.separator();
+
+            let matches = palette_matches(&state.command_input);
+            let (up, down) = ctx.input(|i| (i.key_pressed(Key::ArrowUp), i.key_pressed(Key::ArrowDown)));
+            if down {
+                launcher.selected += 1;
+            }
+            if up {
+                launcher.selected = launcher.selected.saturating_sub(1);
+            }
+            launcher.selected = launcher.selected.min(matches.len().saturating_sub(1));
+
+            let mut chosen = None;
+            ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+                for (index, spec) in matches.iter().enumerate() {
+                    let text = format!("{:<24} {}", spec.usage, spec.description);
+                    if ui.selectable_label(index == launcher.selected, text).clicked() {
+                        chosen = Some(*spec);
+                    }
+                }
+            });
+
+            if enter {
+                // Input with arguments, or that matches nothing, runs as typed
+                let has_args = state.command_input.split_whitespace().nth(1).is_some();
+                match matches.get(launcher.selected) {
+                    Some(spec) if !has_args => chosen = Some(*spec),
+                    _ if !state.command_input.trim().is_empty() => command = Some(state.command_input.clone()),
+                    _ => {}
+                }
+            }
+            // Commands that take arguments are filled in for the user to complete
+            if let Some(spec) = chosen {
+                match spec.usage.find(['<', '[']) {
+                    Some(index) => state.command_input = spec.usage[..index].to_string(),
+                    None => command = Some(spec.usage.to_string()),
+                }
+            }
+
+            if let Some(command) = command {
+                launcher.output_start = Some(state.text_buffer.next_line_number());
+                state.command_input.clear();
+                submit_command(&command, state, sender.clone());
+            } else if escape {
+                state.request_close();
+            }
+        }
+        Some(output_start) => {
+            ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .stick_to_bottom(true)
+                .max_height(ui.available_height() - 32.0)
+                .show(ui, |ui| {
+                    let buffer = &state.text_buffer;
+                    for number in output_start..buffer.next_line_number() {
+                        let Some(line) = buffer.line(number) else {
+                            continue;
+                        };
+                        match buffer.severity(number) {
+                            Some(severity) => ui.colored_label(severity_color(severity), line),
+                            None => ui.label(line),
+                        };
+                    }
+                });
+            ui.separator();
+            ui.label("Press Enter or Esc to dismiss.");
+            if enter || escape {
+                state.request_close();
+            }
+        }
+    });
+
+    state.launcher = Some(launcher);
+    draw_dialogs(ctx, state);
+    draw_close_prompt(ctx, state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(a: &[&str], b: &[&str]) -> (Vec<bool>, Vec<bool>) {
+        let a: Vec<String> = a.iter().map(|line| line.to_string()).collect();
+        let b: Vec<String> = b.iter().map(|line| line.to_string()).collect();
+        diff_lines(&a.iter().collect::<Vec<_>>(), &b.iter().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn diff_lines_marks_nothing_for_identical_input() {
+        assert_eq!(diff(&["a", "b"], &["a", "b"]), (vec![false, false], vec![false, false]));
+    }
+
+    #[test]
+    fn diff_lines_marks_changed_and_inserted_lines() {
+        assert_eq!(
+            diff(&["a", "b", "c"], &["a", "x", "c", "d"]),
+            (vec![false, true, false], vec![false, true, false, true])
+        );
+    }
+
+    #[test]
+    fn diff_lines_handles_empty_sides() {
+        assert_eq!(diff(&[], &["a"]), (vec![], vec![true]));
+        assert_eq!(diff(&["a"], &[]), (vec![true], vec![]));
+    }
+
+    #[test]
+    fn parse_demo_script_reads_each_directive() {
+        let script = "# intro\ntype help\n\nout [BUILD] ok\nwait 250\n";
+        assert_eq!(
+            parse_demo_script(script),
+            Ok(vec![
+                DemoStep::Type("help".to_string()),
+                DemoStep::Output("[BUILD] ok".to_string()),
+                DemoStep::Wait(Duration::from_millis(250)),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_demo_script_rejects_bad_lines() {
+        assert_eq!(parse_demo_script("type"), Err("line 1: 'type' needs a command".to_string()));
+        assert_eq!(parse_demo_script("type   "), Err("line 1: 'type' needs a command".to_string()));
+        assert_eq!(parse_demo_script("wait soon"), Err("line 1: invalid wait 'soon'".to_string()));
+        assert_eq!(parse_demo_script("out a\njump"), Err("line 2: unknown directive 'jump'".to_string()));
+    }
+
+    #[test]
+    fn decode_output_line_strips_terminators_and_progress_redraws() {
+        assert_eq!(decode_output_line(b"done\n"), "done");
+        assert_eq!(decode_output_line(b"done\r\n"), "done");
+        assert_eq!(decode_output_line(b" 10%\r 50%\r100%\r\n"), "100%");
+        assert_eq!(decode_output_line(b"caf\xe9\n"), "caf\u{fffd}");
+        assert_eq!(decode_output_line(b""), "");
+    }
+
+    #[test]
+    fn strip_ansi_removes_vt_sequences() {
+        assert_eq!(strip_ansi(b"\x1b[?25l\x1b[2J\x1b[mhi\x1b[K"), b"hi");
+        assert_eq!(strip_ansi(b"\x1b[1;31merror\x1b[0m: x"), b"error: x");
+        assert_eq!(strip_ansi(b"\x1b]0;C:\\cmd.exe\x07ok"), b"ok");
+        assert_eq!(strip_ansi(b"\x1b]0;title\x1b\\ok"), b"ok");
+        assert_eq!(strip_ansi(b"\x1b="), b"");
+        assert_eq!(strip_ansi(b"plain"), b"plain");
+    }
+
+    #[test]
+    fn quote_windows_arg_follows_argv_rules() {
+        assert_eq!(quote_windows_arg("plain"), "plain");
+        assert_eq!(quote_windows_arg(""), "\"\"");
+        assert_eq!(quote_windows_arg("two words"), "\"two words\"");
+        assert_eq!(quote_windows_arg("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote_windows_arg("C:\\dir with space\\"), "\"C:\\dir with space\\\\\"");
+        assert_eq!(quote_windows_arg("a\\\"b"), "\"a\\\\\\\"b\"");
+    }
+
+    #[test]
+    fn palette_matches_rank_exact_names_first() {
+        let usages = |query| palette_matches(query).iter().map(|spec| spec.usage).collect::<Vec<_>>();
+        assert_eq!(usages("close"), ["close", "confirm-close <on|off>"]);
+        assert_eq!(usages("sta"), ["status", "session-stats"]);
+        assert_eq!(usages("pin x")[0], "pin <command>");
+        assert!(usages("nothing-like-this").is_empty());
+    }
+
+    #[test]
+    fn clear_keeps_line_numbers_unique() {
+        let mut buffer = TextBuffer::new(1000);
+        for line in ["a", "b", "c"] {
+            buffer.add_line(line.to_string());
+        }
+        let mut compare = CompareState::default();
+        compare.select(CompareSide::Left, 0, false);
+        compare.select(CompareSide::Left, 2, true);
+
+        let mut buffer = buffer.cleared();
+        assert_eq!(buffer.next_line_number(), 3);
+        assert_eq!(buffer.line(0), None);
+        buffer.add_line("d".to_string());
+        assert_eq!(buffer.line(3).map(String::as_str), Some("d"));
+
+        compare.update_diff(&buffer);
+        assert_eq!(compare.selection(CompareSide::Left), None);
+    }
+
+    #[test]
+    fn only_background_output_is_highlighted() {
+        let mut buffer = TextBuffer::new(2);
+        buffer.add_line("Tasks failed:    0".to_string());
+        buffer.add_highlighted_line("build failed".to_string(), classify_line("build failed"));
+        assert_eq!(buffer.severity(0), None);
+        assert_eq!(buffer.severity(1), Some(Severity::Error));
+
+        buffer.add_highlighted_line("warning: x".to_string(), classify_line("warning: x"));
+        assert_eq!(buffer.severity(0), None);
+        assert_eq!(buffer.severity(1), Some(Severity::Error));
+        assert_eq!(buffer.severity(2), Some(Severity::Warning));
+    }
+
+    /// Counts what reaches the renderer.
+    #[derive(Default)]
+    struct RecordingRenderer {
+        frames: usize,
+        primitives: usize,
+        textures_set: usize,
+    }
+
+    impl Renderer for RecordingRenderer {
+        fn resize(&mut self, _width: u32, _height: u32) {}
+
+        fn render(&mut self, paint_jobs: &[egui::ClippedPrimitive], textures_delta: &egui::TexturesDelta, _pixels_per_point: f32) {
+            self.frames += 1;
+            self.primitives += paint_jobs.len();
+            self.textures_set += textures_delta.set.len();
+        }
+    }
+
+    #[tokio::test]
+    async fn frames_render_without_a_window() {
+        let (sender, receiver) = mpsc::channel(16);
+        let mut state = AppState::new(receiver, UiWaker(None), None, false);
+        let ctx = Context::default();
+        sender.send(AppMessage::RunCommand("echo hello".to_string())).await.unwrap();
+
+        let mut renderer = RecordingRenderer::default();
+        state.run_frame(&ctx, &mut renderer, &sender, headless_input());
+        assert_eq!(state.text_buffer.line(0).map(String::as_str), Some("> echo hello"));
+        assert_eq!(state.text_buffer.line(1).map(String::as_str), Some("hello"));
+        assert_eq!(renderer.frames, 1);
+        assert!(renderer.primitives > 0);
+        // The font atlas is uploaded with the first frame
+        assert!(renderer.textures_set > 0);
+
+        sender.send(AppMessage::NewLine("build failed".to_string())).await.unwrap();
+        state.run_frame(&ctx, &mut NullRenderer, &sender, headless_input());
+        assert_eq!(state.text_buffer.severity(2), Some(Severity::Error));
+
+        sender.send(AppMessage::Input(egui::Event::Copy)).await.unwrap();
+        state.run_frame(&ctx, &mut NullRenderer, &sender, headless_input());
+        assert!(ctx.input(|i| i.events.contains(&egui::Event::Copy)));
+    }
+
+    /// A deterministic test pattern: gradients with some noise, so it does not compress to nothing.
+    fn test_image(width: u32, height: u32, alpha: bool) -> RgbaImage {
+        let mut seed = 0x2545_f491u32;
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let a = if alpha { (seed >> 24) as u8 } else { 255 };
+                pixels.extend([(x * 255 / width) as u8, (y * 255 / height) as u8, seed as u8, a]);
+            }
+        }
+        RgbaImage { width, height, pixels }
+    }
+
+    #[test]
+    fn inflate_reads_dynamic_huffman_blocks() {
+        // zlib.compress(text, 9) from Python, which picks a dynamic Huffman block here
+        let compressed = [
+            0x78, 0xda, 0xdd, 0xcd, 0xb1, 0x0d, 0x80, 0x30, 0x0c, 0x05, 0xd1, 0x9e, 0x29, 0xfe, 0x04, 0x34, 0x74, 0xa1,
+            0xce, 0x20, 0x16, 0x71, 0x12, 0x4b, 0x51, 0x8c, 0x8c, 0x51, 0x60, 0x7b, 0xd8, 0x80, 0x9e, 0xee, 0x8a, 0x27,
+            0x5d, 0x34, 0x53, 0x0b, 0xf0, 0xca, 0x48, 0x42, 0x4d, 0x0b, 0x32, 0x49, 0xe3, 0x04, 0x57, 0xe8, 0xce, 0x7d,
+            0x46, 0xfc, 0x14, 0x83, 0xac, 0x4b, 0x2f, 0x01, 0xc6, 0x6e, 0xf7, 0x5b, 0x18, 0xe2, 0x15, 0x89, 0x33, 0x9d,
+            0xcd, 0x8f, 0x15, 0x7c, 0x89, 0x63, 0xd3, 0xc4, 0x58, 0xa6, 0xf8, 0xfb, 0xe1, 0x03, 0xd9, 0xa0, 0x77, 0xfe,
+        ];
+        let text = ("Error: the dialog failed to open. ".repeat(2) + "warning: retrying with defaults; exit code 3\n").repeat(3);
+        assert_eq!(zlib_decompress(&compressed).unwrap(), text.as_bytes());
+
+        assert!(zlib_compress(text.as_bytes()).len() < compressed.len() * 2);
+        let data: Vec<u8> = text.bytes().chain(test_image(40, 40, true).pixels).collect();
+        assert_eq!(zlib_decompress(&zlib_compress(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn decode_png_expands_palettes() {
+        // A 3x2 palette image from Python with a tRNS chunk and a Sub-filtered second row
+        let png = [
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00,
+            0x00, 0x03, 0x00, 0x00, 0x00, 0x02, 0x08, 0x03, 0x00, 0x00, 0x00, 0xaa, 0xaa, 0x96, 0x28, 0x00, 0x00, 0x00,
+            0x09, 0x50, 0x4c, 0x54, 0x45, 0xff, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0xff, 0x2d, 0x4a, 0xcd, 0x8a,
+            0x00, 0x00, 0x00, 0x02, 0x74, 0x52, 0x4e, 0x53, 0xff, 0x80, 0x08, 0x0f, 0xb3, 0x6a, 0x00, 0x00, 0x00, 0x10,
+            0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x60, 0x60, 0x64, 0x62, 0x64, 0xfa, 0xcf, 0x08, 0x00, 0x02, 0x21,
+            0x01, 0x07, 0x4f, 0x8c, 0xb7, 0x63, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+        ];
+        let image = decode_png(&png).unwrap();
+        assert_eq!((image.width, image.height), (3, 2));
+        let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 128], [0, 0, 255, 255]);
+        assert_eq!(image.pixels, [red, green, blue, blue, green, blue].concat());
+        assert!(decode_png(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn png_round_trips() {
+        for image in [test_image(37, 23, true), test_image(37, 23, false)] {
+            assert_eq!(decode_png(&encode_png(&image)).unwrap(), image);
+        }
+    }
+
+    #[test]
+    fn downscale_averages_pixels() {
+        let image = RgbaImage { width: 2, height: 2, pixels: [[0, 0, 0, 255], [100, 0, 0, 255], [0, 200, 0, 255], [0, 0, 40, 255]].concat() };
+        assert_eq!(downscale(&image, 1, 1).pixels, [25, 50, 10, 255]);
+        assert_eq!(fit_within(3000, 1000, 1568), (1568, 523));
+        assert_eq!(fit_within(800, 600, 1568), (800, 600));
+    }
+
+    #[test]
+    fn prepare_attachment_resizes_and_caps_size() {
+        let png = encode_png(&test_image(400, 100, false));
+        let attachment = prepare_attachment(&png, 200, usize::MAX).unwrap();
+        assert_eq!((attachment.image.width, attachment.image.height), (200, 50));
+        assert_eq!(attachment.original_size, (400, 100));
+
+        // Noise barely compresses, so meeting the cap takes further scaling
+        let capped = prepare_attachment(&png, 200, 10_000).unwrap();
+        assert!(capped.png.len() <= 10_000);
+        assert!(capped.image.width < 200);
+        assert_eq!(decode_png(&capped.png).unwrap(), capped.image);
+        assert!(prepare_attachment(&png, 200, 10).is_err());
+    }
+
+    #[test]
+    fn compare_drops_evicted_selections() {
+        let mut compare = CompareState::default();
+        compare.select(CompareSide::Left, 2, false);
+        compare.select(CompareSide::Left, 8, true);
+        compare.select(CompareSide::Right, 0, false);
+        compare.select(CompareSide::Right, 3, true);
+        compare.drop_evicted(5);
+        assert_eq!(compare.selection(CompareSide::Left), Some((5, 8)));
+        assert_eq!(compare.selection(CompareSide::Right), None);
+    }
+
+    #[test]
+    fn parse_profiles_reads_sections() {
+        let text = "# work setups\n[Work]\nstartup = run ssh dev\ncheck = host dev:22\ncheck = path /mnt/share\n\n[home]\ncheck = env  DISPLAY\n";
+        let profiles = parse_profiles(text).unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].name, "Work");
+        assert_eq!(profiles[0].startup.as_deref(), Some("run ssh dev"));
+        assert_eq!(profiles[0].checks, [HealthCheck::Host("dev:22".to_string()), HealthCheck::Path("/mnt/share".to_string())]);
+        assert_eq!(profiles[1].name, "home");
+        assert_eq!(profiles[1].startup, None);
+        assert_eq!(profiles[1].checks, [HealthCheck::Env("DISPLAY".to_string())]);
+        assert!(parse_profiles("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_profiles_reports_the_bad_line() {
+        assert_eq!(parse_profiles("startup = time").unwrap_err(), "line 1: entry outside of a [profile] section");
+        assert_eq!(parse_profiles("[a]\n\nport = 22").unwrap_err(), "line 3: unknown entry 'port = 22'");
+        assert!(parse_profiles("[a]\ncheck = ping dev").unwrap_err().starts_with("line 2: invalid check 'ping dev'"));
+        assert!(parse_profiles("[a]\ncheck = host").unwrap_err().starts_with("line 2: invalid check"));
+    }
+
+    fn sha256_hex(chunks: &[&[u8]]) -> String {
+        let mut hasher = Sha256::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finish_hex()
+    }
+
+    #[test]
+    fn sha256_matches_fips_vectors() {
+        assert_eq!(sha256_hex(&[]), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256_hex(&[b"abc"]), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // 56 bytes, so the length no longer fits in the first block
+        assert_eq!(
+            sha256_hex(&[b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"]),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        // Uneven chunks must hash the same as one update
+        let a = vec![b'a'; 1_000_000];
+        let expected = "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0";
+        assert_eq!(sha256_hex(&[&a]), expected);
+        assert_eq!(sha256_hex(&[&a[..1], &a[1..63], &a[63..4097], &a[4097..]]), expected);
+    }
+
+    #[test]
+    fn parse_http_url_splits_authority_and_path() {
+        let url = parse_http_url("http://example.com/files/a.txt?v=1#top").unwrap();
+        assert_eq!((url.authority.as_str(), url.host.as_str(), url.port, url.path.as_str()), ("example.com", "example.com", 80, "/files/a.txt?v=1"));
+
+        let url = parse_http_url("http://h:81?q").unwrap();
+        assert_eq!((url.authority.as_str(), url.host.as_str(), url.port, url.path.as_str()), ("h:81", "h", 81, "/?q"));
+
+        let url = parse_http_url("http://example.com").unwrap();
+        assert_eq!(url.path, "/");
+
+        let url = parse_http_url("http://[::1]:8080/x").unwrap();
+        assert_eq!((url.authority.as_str(), url.host.as_str(), url.port), ("[::1]:8080", "::1", 8080));
+
+        let url = parse_http_url("http://[::1]/x").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 80));
+
+        assert!(parse_http_url("https://example.com/").unwrap_err().contains("https://"));
+        assert!(parse_http_url("ftp://example.com/").is_err());
+        assert!(parse_http_url("http://example.com:http/").is_err());
+        assert!(parse_http_url("http:///path").is_err());
+    }
+
+    #[test]
+    fn resolve_location_handles_absolute_and_relative() {
+        let base = parse_http_url("http://h:81/dir/file.txt").unwrap();
+        assert_eq!(resolve_location(&base, "http://other/x"), "http://other/x");
+        assert_eq!(resolve_location(&base, "/root.txt"), "http://h:81/root.txt");
+        assert_eq!(resolve_location(&base, "next.txt"), "http://h:81/dir/next.txt");
+    }
+
+    #[test]
+    fn parse_fetch_args_defaults_and_options() {
+        let request = parse_fetch_args("http://h/dir/file.tar.gz?x=1").unwrap();
+        assert_eq!((request.url.as_str(), request.output, request.sha256), ("http://h/dir/file.tar.gz?x=1", PathBuf::from("file.tar.gz"), None));
+        assert_eq!(parse_fetch_args("http://h").unwrap().output, PathBuf::from("index.html"));
+        assert_eq!(parse_fetch_args("http://h/dir/").unwrap().output, PathBuf::from("index.html"));
+
+        let hash = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+        let request = parse_fetch_args(&format!("-o out.bin http://h/a --sha256 {}", hash)).unwrap();
+        assert_eq!(request.output, PathBuf::from("out.bin"));
+        assert_eq!(request.sha256.as_deref(), Some(hash.to_ascii_lowercase().as_str()));
+
+        assert!(parse_fetch_args("").is_err());
+        assert!(parse_fetch_args("http://h/a -o").is_err());
+        assert!(parse_fetch_args("http://h/a --sha256 abc").is_err());
+        assert!(parse_fetch_args("http://h/a http://h/b").is_err());
+    }
+
+    fn js_event(kind: u8, number: u8, value: i16) -> [u8; JS_EVENT_SIZE] {
+        let mut event = [0; JS_EVENT_SIZE];
+        event[4..6].copy_from_slice(&value.to_ne_bytes());
+        event[6] = kind;
+        event[7] = number;
+        event
+    }
+
+    #[test]
+    fn gamepad_mapper_fires_once_per_press() {
+        let mut mapper = GamepadMapper::default();
+        assert_eq!(mapper.map(js_event(JS_EVENT_BUTTON, 0, 1)), Some(RemoteAction::RunPin));
+        assert_eq!(mapper.map(js_event(JS_EVENT_BUTTON, 0, 0)), None);
+        assert_eq!(mapper.map(js_event(JS_EVENT_BUTTON | JS_EVENT_INIT, 0, 1)), None);
+        assert_eq!(mapper.map(js_event(JS_EVENT_BUTTON, 5, 1)), Some(RemoteAction::Scroll(REMOTE_PAGE_LINES)));
+
+        // D-pad down, held, released, then pressed again
+        assert_eq!(mapper.map(js_event(JS_EVENT_AXIS, 7, 32_767)), Some(RemoteAction::Scroll(1)));
+        assert_eq!(mapper.map(js_event(JS_EVENT_AXIS, 7, 32_767)), None);
+        assert_eq!(mapper.map(js_event(JS_EVENT_AXIS, 7, 0)), None);
+        assert_eq!(mapper.map(js_event(JS_EVENT_AXIS, 7, 32_767)), Some(RemoteAction::Scroll(1)));
+
+        // A stick drifting between the thresholds does nothing
+        assert_eq!(mapper.map(js_event(JS_EVENT_AXIS, 0, -12_000)), None);
+        assert_eq!(mapper.map(js_event(JS_EVENT_AXIS, 0, -20_000)), Some(RemoteAction::PreviousPin));
+        assert_eq!(mapper.map(js_event(JS_EVENT_AXIS, 0, -12_000)), None);
+
+        // Triggers rest fully negative
+        assert_eq!(mapper.map(js_event(JS_EVENT_AXIS, 2, -32_767)), None);
+    }
+}